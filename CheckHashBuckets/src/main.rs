@@ -1,79 +1,120 @@
-use shellexpand::tilde;
+use rustgit::pack::verify::{check_index, PackReport};
+use rustgit::pack::{Pack, PackIndex};
+use rustgit::Repository;
 
 use std::env;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
+use std::io::{self, Error};
+use std::path::PathBuf;
 
-const HASH_BYTES: usize = 20;
-#[derive(PartialEq, PartialOrd)]
-struct Hash([u8; HASH_BYTES]);
-
-const PACKS_DIRECTORY: &str = "~/RustGit/.git/objects/pack";
-
-// Reads a fixed number of bytes from a stream.
-// Rust's "const generics" make this function very useful.
-fn read_bytes<R: Read, const N: usize>(stream: &mut R) -> io::Result<[u8; N]> {
-    let mut bytes = [0; N];
-    stream.read_exact(&mut bytes)?;
-    Ok(bytes)
-}
-
-// Reads a big-endian 32-bit (4-byte) integer from a stream
-fn read_u32<R: Read>(stream: &mut R) -> io::Result<u32> {
-    let bytes = read_bytes(stream)?;
-    Ok(u32::from_be_bytes(bytes))
+// For each of the 256 possible first bytes `b` of a hash,
+// the fan-out table has the cumulative number of objects with first byte <= `b`.
+// Check that the hashes have the correct first byte and are sorted,
+// and that the index's checksum is right.
+fn read_pack_index(repository: &Repository, file: &str) -> io::Result<()> {
+    let path = repository.packs_dir().join(file);
+    let index = PackIndex::open(&path, repository.object_format())?;
+    let problems = check_index(&index, &path)?;
+    for problem in &problems {
+        println!("{}: {}", file, problem);
+    }
+    if !problems.is_empty() {
+        return Err(Error::other(format!("{}: bad index", file)));
+    }
+    println!("{}: {} objects", file, index.len());
+    Ok(())
 }
 
-// Read an object hash from a stream
-fn read_hash<R: Read>(stream: &mut R) -> io::Result<Hash> {
-    let bytes = read_bytes(stream)?;
-    Ok(Hash(bytes))
+// Checks each index in turn, reporting the ones that are corrupt
+// without stopping, so one bad pack doesn't hide problems in the rest
+fn read_pack_indexes(repository: &Repository, files: &[String]) -> io::Result<()> {
+    let mut bad_files = 0;
+    for file in files {
+        if let Err(e) = read_pack_index(repository, file) {
+            eprintln!("error: {}", e);
+            bad_files += 1;
+        }
+    }
+    if bad_files > 0 {
+        return Err(Error::other(format!(
+            "{} of {} indexes are bad",
+            bad_files,
+            files.len()
+        )));
+    }
+    Ok(())
 }
 
-fn read_pack_index(file: &str) -> io::Result<()> {
-    let mut file = File::open(Path::new(&tilde(PACKS_DIRECTORY).to_string()).join(file))?;
-
-    // Check index header
-    let magic = read_bytes(&mut file)?;
-    assert_eq!(magic, *b"\xfftOc");
-    let version = read_u32(&mut file)?;
-    assert_eq!(version, 2);
-
-    // For each of the 256 possible first bytes `b` of a hash,
-    // read the cumulative number of objects with first byte <= `b`
-    let mut cumulative_objects = [0; 1 << u8::BITS];
-    for objects in &mut cumulative_objects {
-        *objects = read_u32(&mut file)?;
+// Prints each object like `git verify-pack -v`, in the order they are stored:
+// hash, type, size, size in the pack, offset, and for deltas,
+// the length of the delta chain and the hash of the base
+fn print_objects(report: &PackReport) {
+    for object in &report.objects {
+        print!(
+            "{} {:<6} {} {} {}",
+            object.hash,
+            object.object_type.name(),
+            object.size,
+            object.size_in_pack,
+            object.offset
+        );
+        match object.base {
+            Some(base) => println!(" {} {}", object.depth, base),
+            None => println!(),
+        }
     }
 
-    // Read the hash of each of the objects.
-    // Check that the hashes have the correct first byte and are sorted.
-    let mut previous_objects = 0;
-    for (first_byte, &objects) in cumulative_objects.iter().enumerate() {
-        // The difference in the cumulative number of objects
-        // is the number of objects with this first byte
-        let mut previous_hash = None;
-        for _ in 0..(objects - previous_objects) {
-            // We already know the first byte of the hash, so ensure it matches
-            let hash = read_hash(&mut file)?;
-            assert_eq!(hash.0[0], first_byte as u8);
-            if let Some(previous_hash) = previous_hash {
-                assert!(hash > previous_hash);
-            }
-            previous_hash = Some(hash);
+    let plural = |count: usize| if count == 1 { "object" } else { "objects" };
+    for (&depth, &count) in &report.chain_lengths() {
+        match depth {
+            0 => println!("non delta: {} {}", count, plural(count)),
+            _ => println!("chain length = {}: {} {}", depth, count, plural(count)),
         }
-        previous_objects = objects;
     }
-    // `cumulative_objects[255]` is the total number of objects
-    let _total_objects = previous_objects;
+}
 
-    // TODO: read the rest of the index
+// Checks every object in a pack, not just the index
+fn verify_pack(repository: &Repository, file: &str, verbose: bool) -> io::Result<()> {
+    // Accept either the `.idx` or the `.pack` file
+    let index_path = PathBuf::from(file).with_extension("idx");
+    let pack = Pack::open(
+        &repository.packs_dir().join(index_path),
+        repository.object_format(),
+    )?;
+    let report = repository.verify_pack(&pack)?;
+    if verbose {
+        print_objects(&report);
+    }
+    for problem in &report.problems {
+        eprintln!("error: {}", problem);
+    }
+    let status = if report.is_ok() { "ok" } else { "bad" };
+    if verbose || !report.is_ok() {
+        println!("{}: {}", pack.path().display(), status);
+    }
+    if !report.is_ok() {
+        return Err(Error::other(format!("{}: pack is corrupt", file)));
+    }
     Ok(())
 }
 
 fn main() -> io::Result<()> {
     let args: Vec<_> = env::args().collect();
-    let [_, index_file] = <[String; 2]>::try_from(args).unwrap();
-    read_pack_index(&index_file)
+    let usage = || {
+        Error::other(format!(
+            "Usage: {0} INDEX_FILE...\n       {0} verify-pack [-v] INDEX_FILE",
+            args[0]
+        ))
+    };
+    let repository = Repository::discover(env::current_dir()?)?;
+    match &args[1..] {
+        [command, index_file] if command == "verify-pack" => {
+            verify_pack(&repository, index_file, false)
+        }
+        [command, flag, index_file] if command == "verify-pack" && flag == "-v" => {
+            verify_pack(&repository, index_file, true)
+        }
+        [command, ..] if command == "verify-pack" => Err(usage()),
+        [] => Err(usage()),
+        index_files => read_pack_indexes(&repository, index_files),
+    }
 }
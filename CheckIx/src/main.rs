@@ -1,41 +1,30 @@
-use shellexpand::tilde;
+use rustgit::pack::PackIndex;
+use rustgit::Repository;
 
 use std::env;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
-
-const PACKS_DIRECTORY: &str = "~/RustGit/.git/objects/pack";
-
-// Reads a fixed number of bytes from a stream.
-// Rust's "const generics" make this function very useful.
-fn read_bytes<R: Read, const N: usize>(stream: &mut R) -> io::Result<[u8; N]> {
-    let mut bytes = [0; N];
-    stream.read_exact(&mut bytes)?;
-    Ok(bytes)
-}
-
-// Reads a big-endian 32-bit (4-byte) integer from a stream
-fn read_u32<R: Read>(stream: &mut R) -> io::Result<u32> {
-    let bytes = read_bytes(stream)?;
-    Ok(u32::from_be_bytes(bytes))
-}
-
-fn read_pack_index(file: &str) -> io::Result<()> {
-    let mut file = File::open(Path::new(&tilde(PACKS_DIRECTORY).to_string()).join(file))?;
-
-    // Check index header
-    let magic = read_bytes(&mut file)?;
-    assert_eq!(magic, *b"\xfftOc");
-    let version = read_u32(&mut file)?;
-    assert_eq!(version, 2);
-
-    // TODO: read the rest of the index
+use std::io::{self, Error};
+
+// Prints every object in the index like `git show-index`:
+// its offset in the pack, its hash and, for version 2 indexes,
+// the CRC32 of its packed data
+fn read_pack_index(repository: &Repository, file: &str) -> io::Result<()> {
+    let index = PackIndex::open(
+        &repository.packs_dir().join(file),
+        repository.object_format(),
+    )?;
+    for (position, hash) in index.hashes().iter().enumerate() {
+        match index.crc32(position) {
+            Some(crc32) => println!("{} {} ({:08x})", index.offset(position), hash, crc32),
+            None => println!("{} {}", index.offset(position), hash),
+        }
+    }
     Ok(())
 }
 
 fn main() -> io::Result<()> {
     let args: Vec<_> = env::args().collect();
-    let [_, index_file] = <[String; 2]>::try_from(args).unwrap();
-    read_pack_index(&index_file)
+    let [_, index_file] = <[String; 2]>::try_from(args)
+        .map_err(|args| Error::other(format!("Usage: {} INDEX_FILE", args[0])))?;
+    let repository = Repository::discover(env::current_dir()?)?;
+    read_pack_index(&repository, &index_file)
 }
@@ -1,107 +1,40 @@
-use shellexpand::tilde;
-use std::fmt::{self, Display, Formatter};
-use std::fs::{self};
-use std::io::{self, Error, ErrorKind};
-use std::str::{self, FromStr};
-const HASH_BYTES: usize = 20;
+use rustgit::error::RustGitError;
+use rustgit::Repository;
+use std::env;
+use std::io;
 
-const HEAD_FILE: &str = "~/RustGit/.git/HEAD";
-const BRANCH_REFS_DIRECTORY: &str = "~/RustGit/.git/refs/heads/";
-const REF_PREFIX: &str = "ref: refs/heads/";
-// A (commit) hash is a 20-byte identifier.
-// We will see that git also gives hashes to other things.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct Hash([u8; HASH_BYTES]);
-
-// The head is either at a specific commit or a named branch
-enum Head {
-    Commit(Hash),
-    Branch(String),
-}
-
-fn hex_char_value(hex_char: u8) -> Option<u8> {
-    match hex_char {
-        b'0'..=b'9' => Some(hex_char - b'0'),
-        b'a'..=b'f' => Some(hex_char - b'a' + 10),
-        _ => None,
-    }
-}
-
-fn hex_to_hash(hex_hash: &[u8]) -> Option<Hash> {
-    const BITS_PER_CHAR: usize = 4;
-    const CHARS_PER_BYTE: usize = 8 / BITS_PER_CHAR;
-
-    let byte_chunks = hex_hash.chunks_exact(CHARS_PER_BYTE);
-    if !byte_chunks.remainder().is_empty() {
-        return None;
-    }
-
-    let bytes = byte_chunks
-        .map(|hex_digits| {
-            hex_digits.iter().try_fold(0, |value, &byte| {
-                let char_value = hex_char_value(byte)?;
-                Some(value << BITS_PER_CHAR | char_value)
-            })
-        })
-        .collect::<Option<Vec<_>>>()?;
-    let bytes = <[u8; HASH_BYTES]>::try_from(bytes).ok()?;
-    Some(Hash(bytes))
-}
-
-impl FromStr for Hash {
-    type Err = Error;
-    fn from_str(hex_hash: &str) -> io::Result<Self> {
-        hex_to_hash(hex_hash.as_bytes())
-            .ok_or_else(|| Error::new(ErrorKind::Other, format!("Invalid hash: {}", hex_hash)))
-    }
-}
-
-impl Display for Hash {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        // Turn the hash back into a hexadecimal string
-        for byte in self.0 {
-            write!(f, "{:02x}", byte)?;
-        }
-        Ok(())
+fn main() -> io::Result<()> {
+    let repository = Repository::discover(env::current_dir()?)?;
+    // `--short` prints the shortest unambiguous abbreviation, like `git rev-parse --short`
+    let mut args: Vec<_> = env::args().skip(1).collect();
+    let short = args.first().is_some_and(|arg| arg == "--short");
+    if short {
+        args.remove(0);
     }
-}
-
-fn get_head() -> io::Result<Head> {
-    use Head::*;
-
-    let hash_contents = fs::read_to_string(tilde(HEAD_FILE).to_string())?;
-    // Remove trailing newline
-    let hash_contents = hash_contents.trim_end();
-    // If .git/HEAD starts with `ref: refs/heads/`, it's a branch name.
-    // Otherwise, it should be a commit hash.
-    Ok(match hash_contents.strip_prefix(REF_PREFIX) {
-        Some(branch) => Branch(branch.to_string()),
-        _ => {
-            let hash = Hash::from_str(hash_contents)?;
-            Commit(hash)
-        }
-    })
-}
-
-impl Head {
-    fn get_hash(&self) -> io::Result<Hash> {
-        use Head::*;
 
-        match self {
-            Commit(hash) => Ok(*hash),
-            Branch(branch) => {
-                // Copied from get_branch_head()
-                let ref_file = tilde(BRANCH_REFS_DIRECTORY).to_string() + branch;
-                let hash_contents = fs::read_to_string(ref_file)?;
-                Hash::from_str(hash_contents.trim_end())
-            }
+    // Look up the revision given on the command line, e.g. `main~2`, or HEAD
+    let (label, hash) = match args.first() {
+        Some(revision) => (revision.clone(), repository.rev_parse(revision)?),
+        None => {
+            let head = repository.get_head()?;
+            let hash = match head.get_hash(&repository) {
+                Ok(hash) => hash,
+                // A new repository's branch has nothing to show yet
+                Err(e) => match RustGitError::from_io_error(&e) {
+                    Some(RustGitError::UnbornBranch(branch)) => {
+                        println!("On branch {}: no commits yet", branch);
+                        return Ok(());
+                    }
+                    _ => return Err(e),
+                },
+            };
+            ("Head".to_string(), hash)
         }
+    };
+    if short {
+        println!("{} hash: {}", label, repository.abbreviate_hash(&hash)?);
+    } else {
+        println!("{} hash: {}", label, hash);
     }
-}
-
-fn main() -> io::Result<()> {
-    let head = get_head()?;
-    let head_hash = head.get_hash()?;
-    println!("Head hash: {}", head_hash);
     Ok(())
 }
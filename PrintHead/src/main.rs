@@ -1,15 +1,15 @@
-use shellexpand::tilde;
+use rustgit::Repository;
+use std::env;
 use std::fs;
 use std::io;
 
-const HEAD_FILE: &str = "~/RustGit/.git/HEAD";
-
-fn get_head() -> io::Result<String> {
-    fs::read_to_string(tilde(HEAD_FILE).to_string())
+fn get_head(repository: &Repository) -> io::Result<String> {
+    fs::read_to_string(repository.head_file())
 }
 
 fn main() -> io::Result<()> {
-    let head = get_head()?;
+    let repository = Repository::discover(env::current_dir()?)?;
+    let head = get_head(&repository)?;
     println!("Head file: {:?}", head);
     Ok(())
 }
@@ -0,0 +1,318 @@
+use std::collections::BTreeSet;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+
+use crate::error::RustGitError;
+use crate::hash::{common_prefix_chars, hex_to_hash, Hash, HashPrefix};
+use crate::object::ObjectType;
+use crate::pack::index::fanout_range;
+use crate::repository::Repository;
+
+// How long abbreviated hashes should be, or "auto" to base it on the number of objects
+const ABBREV_CONFIG: &str = "core.abbrev";
+// Abbreviations are never shorter than this when the length is picked automatically
+const DEFAULT_ABBREV_CHARS: usize = 7;
+
+/// An abbreviated hash that matches more than one object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ambiguous {
+    pub prefix: HashPrefix,
+    /// Each matching object and its type, if it could be read
+    pub candidates: Vec<(Hash, Option<ObjectType>)>,
+}
+
+impl Display for Ambiguous {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Short hash {} is ambiguous. The candidates are:",
+            self.prefix
+        )?;
+        for (hash, object_type) in &self.candidates {
+            match object_type {
+                Some(object_type) => write!(f, "\n  {} {}", hash, object_type)?,
+                None => write!(f, "\n  {} (unreadable)", hash)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Ambiguous {}
+
+impl From<Ambiguous> for Error {
+    fn from(ambiguous: Ambiguous) -> Error {
+        RustGitError::AmbiguousRevision(ambiguous).into()
+    }
+}
+
+impl Repository {
+    // The hashes of the loose objects whose hashes start with a byte,
+    // which are the files in the directory named after that byte
+    fn loose_hashes(&self, first_byte: u8) -> io::Result<Vec<Hash>> {
+        let directory_name = format!("{:02x}", first_byte);
+        let entries = match fs::read_dir(self.objects_dir().join(&directory_name)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut hashes = vec![];
+        for entry in entries {
+            let file_name = entry?.file_name();
+            let hex_hash = directory_name.clone() + &file_name.to_string_lossy();
+            // Skip temporary files and anything else that isn't an object
+            if let Some(hash) = hex_to_hash(hex_hash.as_bytes()) {
+                hashes.push(hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Finds every object, loose or packed, whose hash starts with a prefix
+    pub fn find_objects_with_prefix(&self, prefix: &HashPrefix) -> io::Result<Vec<Hash>> {
+        let mut matches = BTreeSet::new();
+        for hash in self.loose_hashes(prefix.first_byte())? {
+            if prefix.matches(&hash) {
+                matches.insert(hash);
+            }
+        }
+
+        // The fan-out table narrows each index down to the hashes with the
+        // same first byte. Those are sorted, so the matches are all together,
+        // starting from the first hash not less than the prefix padded with 0s.
+        // A prefix longer than the repository's hashes can't match any of them.
+        let Some(lower_bound) = prefix.lower_bound(self.object_format()) else {
+            return Ok(matches.into_iter().collect());
+        };
+        for pack in self.packs()? {
+            let index = pack.index();
+            let Some(bucket) = index
+                .hashes()
+                .get(fanout_range(index.fanout(), prefix.first_byte()))
+            else {
+                continue;
+            };
+            let start = bucket.partition_point(|hash| hash < &lower_bound);
+            matches.extend(
+                bucket[start..]
+                    .iter()
+                    .take_while(|hash| prefix.matches(hash)),
+            );
+        }
+        Ok(matches.into_iter().collect())
+    }
+
+    /// Finds the object an abbreviated hash refers to.
+    /// Returns an `Ambiguous` error, listing the candidates,
+    /// if the prefix matches more than one object.
+    pub fn resolve_abbreviated_hash(&self, prefix: &HashPrefix) -> io::Result<Hash> {
+        let matches = self.find_objects_with_prefix(prefix)?;
+        match matches.as_slice() {
+            [] => Err(RustGitError::PrefixNotFound(prefix.clone()).into()),
+            &[hash] => Ok(hash),
+            _ => {
+                let candidates = matches
+                    .into_iter()
+                    .map(|hash| {
+                        let object_type = self.read_object_header(hash).ok();
+                        (hash, object_type.map(|(object_type, _)| object_type))
+                    })
+                    .collect();
+                Err(Ambiguous {
+                    prefix: prefix.clone(),
+                    candidates,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// The length to abbreviate hashes to, from `core.abbrev`.
+    /// When it is "auto" or unset, the length grows with the number of objects
+    /// so that abbreviations are unlikely to become ambiguous, like git does.
+    pub fn default_abbrev_len(&self) -> io::Result<usize> {
+        match self.config().get(ABBREV_CONFIG) {
+            None | Some("auto") => {}
+            Some("no") => return Ok(self.object_format().hex_len()),
+            Some(length) => {
+                if let Ok(length) = length.parse::<usize>() {
+                    return Ok(length.clamp(4, self.object_format().hex_len()));
+                }
+            }
+        }
+
+        // With about 2^bits objects, a collision is expected among
+        // the first bits/2 bits of the hashes, and each character holds 4 bits
+        let objects: usize = self.packs()?.iter().map(|pack| pack.index().len()).sum();
+        let bits = (usize::BITS - objects.leading_zeros()) as usize;
+        Ok(bits.div_ceil(2).max(DEFAULT_ABBREV_CHARS))
+    }
+
+    /// Shortens a hash as much as possible without matching any other object,
+    /// but not below the `core.abbrev` length
+    pub fn abbreviate_hash(&self, hash: &Hash) -> io::Result<String> {
+        let min_len = self.default_abbrev_len()?;
+        let hex_hash = hash.to_string();
+        // Only the objects sharing the first byte could share a longer prefix
+        let mut shared_chars = 0;
+        let mut neighbors = self.loose_hashes(hash.first_byte())?;
+        for pack in self.packs()? {
+            let index = pack.index();
+            if let Some(bucket) = index
+                .hashes()
+                .get(fanout_range(index.fanout(), hash.first_byte()))
+            {
+                // In sorted order, the hashes sharing the most characters
+                // are the ones either side of where this hash goes
+                let position = bucket.partition_point(|other| other < hash);
+                let after = bucket[position..].iter().find(|other| *other != hash);
+                neighbors.extend(position.checked_sub(1).map(|before| bucket[before]));
+                neighbors.extend(after);
+            }
+        }
+        for other in neighbors.iter().filter(|other| *other != hash) {
+            shared_chars = shared_chars.max(common_prefix_chars(hash, other));
+        }
+        let len = (shared_chars + 1).max(min_len).min(hex_hash.len());
+        Ok(hex_hash[..len].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    // A blob and a tree whose hashes share their first 5 characters, "87486"
+    const BLOB: &[u8] = b"blob 36\n";
+    const TREE: &[u8] = b"tree 3760\n";
+
+    fn prefix(hex_prefix: &str) -> HashPrefix {
+        HashPrefix::parse(hex_prefix).unwrap()
+    }
+
+    // A repository with the blob loose and the tree packed
+    fn write_objects(test_repo: &TestRepo) -> (Hash, Hash) {
+        let blob = test_repo.write_object(ObjectType::Blob, BLOB);
+        let pack = test_repo.write_pack(&[(ObjectType::Tree, TREE)]);
+        (blob, pack.objects[0].0)
+    }
+
+    #[test]
+    fn finds_loose_and_packed_objects() {
+        let test_repo = TestRepo::new();
+        let (blob, tree) = write_objects(&test_repo);
+        test_repo.write_object(ObjectType::Blob, b"unrelated\n");
+        let repository = test_repo.open();
+
+        let both = vec![tree, blob];
+        assert_eq!(
+            repository
+                .find_objects_with_prefix(&prefix("8748"))
+                .unwrap(),
+            both
+        );
+        assert_eq!(
+            repository
+                .find_objects_with_prefix(&prefix("87486"))
+                .unwrap(),
+            both
+        );
+        assert_eq!(
+            repository
+                .find_objects_with_prefix(&prefix("874869"))
+                .unwrap(),
+            vec![blob]
+        );
+        assert_eq!(
+            repository
+                .find_objects_with_prefix(&prefix("874864"))
+                .unwrap(),
+            vec![tree]
+        );
+        assert_eq!(
+            repository
+                .find_objects_with_prefix(&prefix("874860"))
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn ambiguous_prefix_lists_every_candidate() {
+        let test_repo = TestRepo::new();
+        let (blob, tree) = write_objects(&test_repo);
+        let repository = test_repo.open();
+
+        let error = repository
+            .resolve_abbreviated_hash(&prefix("87486"))
+            .unwrap_err();
+        let Some(RustGitError::AmbiguousRevision(ambiguous)) = RustGitError::from_io_error(&error)
+        else {
+            panic!("expected an ambiguous revision, got {:?}", error);
+        };
+        // In hash order, and the tree's hash starts 874864
+        let candidates = vec![
+            (tree, Some(ObjectType::Tree)),
+            (blob, Some(ObjectType::Blob)),
+        ];
+        assert_eq!(
+            *ambiguous,
+            Ambiguous {
+                prefix: prefix("87486"),
+                candidates,
+            }
+        );
+
+        assert_eq!(
+            repository
+                .resolve_abbreviated_hash(&prefix("874869"))
+                .unwrap(),
+            blob
+        );
+        let error = repository
+            .resolve_abbreviated_hash(&prefix("874860"))
+            .unwrap_err();
+        assert!(matches!(
+            RustGitError::from_io_error(&error),
+            Some(RustGitError::PrefixNotFound(missing)) if *missing == prefix("874860")
+        ));
+    }
+
+    #[test]
+    fn abbrev_len_comes_from_core_abbrev() {
+        let test_repo = TestRepo::new();
+        assert_eq!(
+            test_repo.open().default_abbrev_len().unwrap(),
+            DEFAULT_ABBREV_CHARS
+        );
+        for (abbrev, len) in [("auto", 7), ("10", 10), ("2", 4), ("99", 40), ("no", 40)] {
+            test_repo.write("config", format!("[core]\n\tabbrev = {}\n", abbrev));
+            assert_eq!(
+                test_repo.open().default_abbrev_len().unwrap(),
+                len,
+                "{}",
+                abbrev
+            );
+        }
+    }
+
+    #[test]
+    fn abbreviations_are_as_short_as_they_can_be() {
+        let test_repo = TestRepo::new();
+        let (blob, tree) = write_objects(&test_repo);
+        let unrelated = test_repo.write_object(ObjectType::Blob, b"unrelated\n");
+        test_repo.write("config", "[core]\n\tabbrev = 4\n");
+        let repository = test_repo.open();
+
+        // One more character than the 5 the blob and tree share
+        assert_eq!(repository.abbreviate_hash(&blob).unwrap(), "874869");
+        assert_eq!(repository.abbreviate_hash(&tree).unwrap(), "874864");
+        assert_eq!(
+            repository.abbreviate_hash(&unrelated).unwrap(),
+            unrelated.to_string()[..4]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// The multi-pack-index and commit-graph files share a layout:
+// a header, then a table of contents saying where each chunk starts,
+// then the chunks themselves, then a checksum
+
+use crate::pack::{read_bytes, read_u64};
+
+// Each chunk is named by 4 ASCII characters, e.g. "OIDF"
+pub type ChunkId = [u8; 4];
+
+// Reads the table of contents starting at `table_offset`.
+// It has an ID and offset for each chunk, followed by an entry
+// with ID 0 giving the offset where the last chunk ends.
+// Returns each chunk's ID and contents, or None if the table is malformed.
+pub fn read_chunks(
+    data: &[u8],
+    table_offset: usize,
+    chunk_count: usize,
+) -> Option<Vec<(ChunkId, &[u8])>> {
+    let mut table = data.get(table_offset..)?;
+    let mut entries = Vec::with_capacity(chunk_count + 1);
+    for _ in 0..=chunk_count {
+        let id: ChunkId = read_bytes(&mut table).ok()?;
+        let offset = usize::try_from(read_u64(&mut table).ok()?).ok()?;
+        entries.push((id, offset));
+    }
+    if entries[chunk_count].0 != [0; 4] {
+        return None;
+    }
+
+    (0..chunk_count)
+        .map(|chunk| {
+            let (id, start) = entries[chunk];
+            let (_, end) = entries[chunk + 1];
+            Some((id, data.get(start..end)?))
+        })
+        .collect()
+}
+
+// Finds the chunk with the given ID
+pub fn find_chunk<'a>(chunks: &[(ChunkId, &'a [u8])], id: ChunkId) -> Option<&'a [u8]> {
+    chunks
+        .iter()
+        .find(|(chunk_id, _)| *chunk_id == id)
+        .map(|&(_, contents)| contents)
+}
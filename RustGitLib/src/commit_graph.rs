@@ -0,0 +1,663 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::chunk::{find_chunk, read_chunks};
+use crate::error::RustGitError;
+use crate::hash::{Hash, ObjectFormat};
+use crate::object::parse_commit;
+use crate::pack::index::{fanout_search, FANOUT_ENTRIES};
+use crate::pack::{read_bytes, read_hash, read_u32, read_u64};
+use crate::repository::Repository;
+
+const GRAPH_MAGIC: [u8; 4] = *b"CGPH";
+// The magic number and 4 bytes of versions and counts
+const HEADER_BYTES: usize = 8;
+
+const FANOUT_CHUNK: [u8; 4] = *b"OIDF";
+const HASHES_CHUNK: [u8; 4] = *b"OIDL";
+const COMMIT_DATA_CHUNK: [u8; 4] = *b"CDAT";
+const EXTRA_EDGES_CHUNK: [u8; 4] = *b"EDGE";
+const GENERATION_DATA_CHUNK: [u8; 4] = *b"GDA2";
+const GENERATION_DATA_OVERFLOW_CHUNK: [u8; 4] = *b"GDO2";
+const BASE_GRAPHS_CHUNK: [u8; 4] = *b"BASE";
+
+// A parent position meaning there is no parent
+const NO_PARENT: u32 = 0x7000_0000;
+// A second parent with this bit set is instead the start of a list
+// of parents in the extra edges chunk. The last one in the list has it set too.
+const EXTRA_EDGES_FLAG: u32 = 1 << 31;
+// The commit time is the low 34 bits of the last 8 bytes,
+// and the topological level is the other 30
+const COMMIT_TIME_BITS: u32 = 34;
+// A generation data offset with this bit set is instead
+// an index into the overflow chunk
+const GENERATION_OVERFLOW_FLAG: u32 = 1 << 31;
+
+/// The generation number of commits that aren't in the commit-graph
+pub const GENERATION_NUMBER_INFINITY: u64 = u64::MAX;
+
+// Whether git should use the commit-graph, on by default
+const COMMIT_GRAPH_CONFIG: &str = "core.commitGraph";
+
+/// What a commit-graph records about a commit,
+/// which is enough to walk history without reading commit objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub tree: Hash,
+    pub parents: Vec<Hash>,
+    /// Seconds since the Unix epoch
+    pub commit_time: u64,
+    /// Never less than any parent's generation number.
+    /// This is the corrected commit date if every file in the graph has them,
+    /// or else the topological level.
+    pub generation: u64,
+}
+
+// One file in a commit-graph
+#[derive(Debug)]
+struct GraphFile {
+    path: PathBuf,
+    format: ObjectFormat,
+    fanout: [u32; FANOUT_ENTRIES],
+    hashes: Vec<Hash>,
+    commit_data: Vec<u8>,
+    extra_edges: Vec<u32>,
+    // The corrected commit date offsets, if the file has them
+    generation_data: Option<Vec<u32>>,
+    generation_data_overflow: Vec<u64>,
+    // The checksums of the files below this one in a chain
+    base_graphs: Vec<Hash>,
+}
+
+fn corrupt_graph(path: &Path, reason: &str) -> Error {
+    RustGitError::CorruptCommitGraph {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    }
+    .into()
+}
+
+// Each commit's data is its tree, 2 parent positions,
+// and 8 bytes holding its generation number and commit time
+fn commit_data_bytes(format: ObjectFormat) -> usize {
+    format.hash_bytes() + 16
+}
+
+// Reads a chunk of big-endian 32-bit integers
+fn read_u32_table(mut chunk: &[u8]) -> io::Result<Vec<u32>> {
+    (0..chunk.len() / 4).map(|_| read_u32(&mut chunk)).collect()
+}
+
+impl GraphFile {
+    fn open(path: &Path, format: ObjectFormat) -> io::Result<GraphFile> {
+        let data = fs::read(path)?;
+        let corrupt = |reason: &str| corrupt_graph(path, reason);
+
+        let mut header = data
+            .get(..HEADER_BYTES)
+            .ok_or_else(|| corrupt("file is too short"))?;
+        let magic = read_bytes(&mut header)?;
+        if magic != GRAPH_MAGIC {
+            return Err(corrupt("wrong magic number"));
+        }
+        let [version, hash_version, chunk_count, base_count] = read_bytes(&mut header)?;
+        if version != 1 {
+            return Err(corrupt(&format!("unsupported version {}", version)));
+        }
+        if hash_version != format.hash_version() {
+            return Err(corrupt(&format!(
+                "unsupported hash version {}",
+                hash_version
+            )));
+        }
+
+        let chunks = read_chunks(&data, HEADER_BYTES, chunk_count as usize)
+            .ok_or_else(|| corrupt("chunk table is invalid"))?;
+        let required_chunk = |id| {
+            find_chunk(&chunks, id)
+                .ok_or_else(|| corrupt(&format!("missing {} chunk", String::from_utf8_lossy(&id))))
+        };
+
+        let mut fanout_chunk = required_chunk(FANOUT_CHUNK)?;
+        let mut fanout = [0; FANOUT_ENTRIES];
+        for commits in &mut fanout {
+            *commits = read_u32(&mut fanout_chunk)?;
+        }
+        // `fanout[255]` is the total number of commits
+        let total_commits = fanout[FANOUT_ENTRIES - 1] as usize;
+
+        let mut hashes_chunk = required_chunk(HASHES_CHUNK)?;
+        if hashes_chunk.len() != total_commits * format.hash_bytes() {
+            return Err(corrupt("hash table has the wrong size"));
+        }
+        let hashes = (0..total_commits)
+            .map(|_| read_hash(&mut hashes_chunk, format))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let commit_data = required_chunk(COMMIT_DATA_CHUNK)?;
+        if commit_data.len() != total_commits * commit_data_bytes(format) {
+            return Err(corrupt("commit data has the wrong size"));
+        }
+        // Only octopus merges need extra edges
+        let extra_edges =
+            read_u32_table(find_chunk(&chunks, EXTRA_EDGES_CHUNK).unwrap_or_default())?;
+
+        let generation_data = find_chunk(&chunks, GENERATION_DATA_CHUNK)
+            .map(read_u32_table)
+            .transpose()?;
+        if generation_data
+            .as_ref()
+            .is_some_and(|generation_data| generation_data.len() != total_commits)
+        {
+            return Err(corrupt("generation data has the wrong size"));
+        }
+        let mut overflow_chunk =
+            find_chunk(&chunks, GENERATION_DATA_OVERFLOW_CHUNK).unwrap_or_default();
+        let generation_data_overflow = (0..overflow_chunk.len() / 8)
+            .map(|_| read_u64(&mut overflow_chunk))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut base_graphs_chunk = find_chunk(&chunks, BASE_GRAPHS_CHUNK).unwrap_or_default();
+        let base_graphs = (0..base_count)
+            .map(|_| read_hash(&mut base_graphs_chunk, format))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(|_| corrupt("missing base graphs"))?;
+
+        Ok(GraphFile {
+            path: path.to_path_buf(),
+            format,
+            fanout,
+            hashes,
+            commit_data: commit_data.to_vec(),
+            extra_edges,
+            generation_data,
+            generation_data_overflow,
+            base_graphs,
+        })
+    }
+}
+
+/// A commit-graph: either the single `objects/info/commit-graph` file
+/// or a chain of files in `objects/info/commit-graphs`.
+/// In a chain, each file adds the commits that aren't in the files below it,
+/// and commits are numbered across the whole chain, starting from the bottom.
+#[derive(Debug)]
+pub struct CommitGraph {
+    // The bottom of the chain comes first
+    files: Vec<GraphFile>,
+    // Whether generations are corrected commit dates rather than topological levels.
+    // Like git, they are only used if every file has them,
+    // since the two kinds of generation can't be compared with each other.
+    corrected_dates: bool,
+}
+
+impl CommitGraph {
+    /// Opens a single commit-graph file of a repository using `format` for its hashes
+    pub fn open(path: &Path, format: ObjectFormat) -> io::Result<CommitGraph> {
+        let file = GraphFile::open(path, format)?;
+        if !file.base_graphs.is_empty() {
+            return Err(corrupt_graph(path, "a single file can't have base graphs"));
+        }
+        Ok(CommitGraph::new(vec![file]))
+    }
+
+    /// Opens the chain of commit-graph files listed in `commit-graph-chain`,
+    /// which has the checksum of each file in order, starting from the bottom
+    pub fn open_chain(graphs_dir: &Path, format: ObjectFormat) -> io::Result<CommitGraph> {
+        let chain_path = graphs_dir.join("commit-graph-chain");
+        let chain = fs::read_to_string(&chain_path)?;
+        let mut files = vec![];
+        let mut checksums = vec![];
+        for line in chain.lines() {
+            let checksum: Hash = line.trim().parse()?;
+            let path = graphs_dir.join(format!("graph-{}.graph", checksum));
+            let file = GraphFile::open(&path, format)?;
+            // Each file lists the files below it, which must match the chain
+            if file.base_graphs != checksums {
+                return Err(corrupt_graph(&path, "base graphs differ from the chain"));
+            }
+            files.push(file);
+            checksums.push(checksum);
+        }
+        Ok(CommitGraph::new(files))
+    }
+
+    fn new(files: Vec<GraphFile>) -> CommitGraph {
+        let corrected_dates = files.iter().all(|file| file.generation_data.is_some());
+        CommitGraph {
+            files,
+            corrected_dates,
+        }
+    }
+
+    /// The number of commits in the graph
+    pub fn len(&self) -> usize {
+        self.files.iter().map(|file| file.hashes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Finds a commit's position in the graph
+    pub fn position(&self, hash: &Hash) -> Option<usize> {
+        let mut base_commits = 0;
+        for file in &self.files {
+            if let Some(position) = fanout_search(&file.fanout, &file.hashes, hash) {
+                return Some(base_commits + position);
+            }
+            base_commits += file.hashes.len();
+        }
+        None
+    }
+
+    // Finds the file holding a position, and the position within that file
+    fn file_position(&self, mut position: usize) -> Option<(&GraphFile, usize)> {
+        for file in &self.files {
+            if position < file.hashes.len() {
+                return Some((file, position));
+            }
+            position -= file.hashes.len();
+        }
+        None
+    }
+
+    /// The hash of the commit at a position
+    pub fn hash(&self, position: usize) -> Option<Hash> {
+        let (file, position) = self.file_position(position)?;
+        Some(file.hashes[position])
+    }
+
+    /// Looks up a commit, returning None if it isn't in the graph
+    pub fn get(&self, hash: &Hash) -> io::Result<Option<GraphCommit>> {
+        match self.position(hash) {
+            Some(position) => self.commit(position).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads what the graph records about the commit at a position
+    pub fn commit(&self, position: usize) -> io::Result<GraphCommit> {
+        let (file, file_position) = self
+            .file_position(position)
+            .ok_or(RustGitError::CommitPositionOutOfRange(position))?;
+        let corrupt = |reason: &str| corrupt_graph(&file.path, reason);
+        let mut data = &file.commit_data[file_position * commit_data_bytes(file.format)..];
+        let tree = read_hash(&mut data, file.format)?;
+        let first_parent = read_u32(&mut data)?;
+        let second_parent = read_u32(&mut data)?;
+        let generation_and_time = read_u64(&mut data)?;
+
+        let mut parent_positions = vec![];
+        if first_parent != NO_PARENT {
+            parent_positions.push(first_parent);
+        }
+        if second_parent & EXTRA_EDGES_FLAG != 0 {
+            // Octopus merges list the second and later parents in the extra edges
+            let mut edges = file
+                .extra_edges
+                .get((second_parent & !EXTRA_EDGES_FLAG) as usize..)
+                .ok_or_else(|| corrupt("extra edge is out of range"))?
+                .iter();
+            loop {
+                let &edge = edges
+                    .next()
+                    .ok_or_else(|| corrupt("extra edge list is not terminated"))?;
+                parent_positions.push(edge & !EXTRA_EDGES_FLAG);
+                if edge & EXTRA_EDGES_FLAG != 0 {
+                    break;
+                }
+            }
+        } else if second_parent != NO_PARENT {
+            parent_positions.push(second_parent);
+        }
+        let parents = parent_positions
+            .into_iter()
+            .map(|parent| {
+                self.hash(parent as usize)
+                    .ok_or_else(|| corrupt("parent position is out of range"))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let commit_time = generation_and_time & ((1 << COMMIT_TIME_BITS) - 1);
+        let generation_data = file
+            .generation_data
+            .as_ref()
+            .filter(|_| self.corrected_dates);
+        let generation = match generation_data {
+            // The corrected commit date is stored as an offset from the commit time
+            Some(generation_data) => {
+                let offset = generation_data[file_position];
+                let offset = if offset & GENERATION_OVERFLOW_FLAG == 0 {
+                    offset as u64
+                } else {
+                    *file
+                        .generation_data_overflow
+                        .get((offset & !GENERATION_OVERFLOW_FLAG) as usize)
+                        .ok_or_else(|| corrupt("generation overflow is out of range"))?
+                };
+                commit_time
+                    .checked_add(offset)
+                    .ok_or_else(|| corrupt("generation overflow is out of range"))?
+            }
+            None => generation_and_time >> COMMIT_TIME_BITS,
+        };
+        Ok(GraphCommit {
+            tree,
+            parents,
+            commit_time,
+            generation,
+        })
+    }
+}
+
+impl Repository {
+    // Reads the commit-graph the first time it is needed,
+    // keeping the error instead if it is corrupt
+    fn load_commit_graph(&self) -> &io::Result<Option<CommitGraph>> {
+        self.commit_graph.get_or_init(|| {
+            if self.config().get_bool(COMMIT_GRAPH_CONFIG) == Some(false) {
+                return Ok(None);
+            }
+            let info_dir = self.objects_dir().join("info");
+            let commit_graph =
+                match CommitGraph::open(&info_dir.join("commit-graph"), self.object_format()) {
+                    Err(e) if e.kind() == ErrorKind::NotFound => CommitGraph::open_chain(
+                        &info_dir.join("commit-graphs"),
+                        self.object_format(),
+                    ),
+                    result => result,
+                };
+            match commit_graph {
+                Ok(commit_graph) => Ok(Some(commit_graph)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    /// The commit-graph, loaded the first time it is needed.
+    /// A single `objects/info/commit-graph` file is used if there is one,
+    /// otherwise the chain in `objects/info/commit-graphs`.
+    /// Returns None if there is neither or `core.commitGraph` is off.
+    /// Like git, a corrupt graph is ignored, so commits are read from
+    /// their objects instead; `commit_graph_error()` says what was wrong.
+    pub fn commit_graph(&self) -> Option<&CommitGraph> {
+        self.load_commit_graph().as_ref().ok()?.as_ref()
+    }
+
+    /// Why the commit-graph was ignored, if it is corrupt
+    pub fn commit_graph_error(&self) -> Option<&Error> {
+        self.load_commit_graph().as_ref().err()
+    }
+
+    /// Reads a commit's tree, parents and commit time.
+    /// These come from the commit-graph if it has the commit,
+    /// which saves inflating and parsing the commit object.
+    /// Commits outside the graph have a generation of `GENERATION_NUMBER_INFINITY`.
+    pub fn read_graph_commit(&self, hash: Hash) -> io::Result<GraphCommit> {
+        // A commit the graph can't describe, because its entry is corrupt,
+        // is read from its object as if the graph didn't have it
+        if let Some(commit_graph) = self.commit_graph() {
+            if let Ok(Some(commit)) = commit_graph.get(&hash) {
+                return Ok(commit);
+            }
+        }
+
+        let object = self.read_object(hash)?;
+        let commit = parse_commit(&object, self.object_format()).map_err(|e| e.in_object(hash))?;
+        Ok(GraphCommit {
+            tree: commit.tree,
+            parents: commit.parents,
+            commit_time: commit.committer.time,
+            generation: GENERATION_NUMBER_INFINITY,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::test_repo::{chunk_file, fanout_bytes, TestRepo};
+
+    const FORMAT: ObjectFormat = ObjectFormat::Sha1;
+
+    // A commit for a graph file: its hash, parent position,
+    // commit time, topological level and corrected date offset
+    struct TestCommit {
+        hash: Hash,
+        parent: u32,
+        time: u64,
+        level: u64,
+        date_offset: u32,
+    }
+
+    // Writes one file of a chain, returning its checksum.
+    // `commits` must be sorted by hash.
+    fn write_graph_file(
+        test_repo: &TestRepo,
+        commits: &[TestCommit],
+        base_graphs: &[Hash],
+        generation_data: bool,
+        generation_overflow: &[u64],
+    ) -> Hash {
+        let format = test_repo.format();
+        let hashes: Vec<Hash> = commits.iter().map(|commit| commit.hash).collect();
+        let tree = format.digest(b"tree");
+        let mut commit_data = vec![];
+        for commit in commits {
+            commit_data.extend_from_slice(tree.as_bytes());
+            commit_data.extend_from_slice(&commit.parent.to_be_bytes());
+            commit_data.extend_from_slice(&NO_PARENT.to_be_bytes());
+            commit_data
+                .extend_from_slice(&(commit.level << COMMIT_TIME_BITS | commit.time).to_be_bytes());
+        }
+        let mut chunks = vec![
+            (FANOUT_CHUNK, fanout_bytes(&hashes)),
+            (
+                HASHES_CHUNK,
+                hashes
+                    .iter()
+                    .flat_map(|hash| hash.as_bytes().to_vec())
+                    .collect(),
+            ),
+            (COMMIT_DATA_CHUNK, commit_data),
+        ];
+        if generation_data {
+            chunks.push((
+                GENERATION_DATA_CHUNK,
+                commits
+                    .iter()
+                    .flat_map(|commit| commit.date_offset.to_be_bytes())
+                    .collect(),
+            ));
+        }
+        if !generation_overflow.is_empty() {
+            chunks.push((
+                GENERATION_DATA_OVERFLOW_CHUNK,
+                generation_overflow
+                    .iter()
+                    .flat_map(|offset| offset.to_be_bytes())
+                    .collect(),
+            ));
+        }
+        if !base_graphs.is_empty() {
+            chunks.push((
+                BASE_GRAPHS_CHUNK,
+                base_graphs
+                    .iter()
+                    .flat_map(|hash| hash.as_bytes().to_vec())
+                    .collect(),
+            ));
+        }
+        let mut header = GRAPH_MAGIC.to_vec();
+        header.extend_from_slice(&[
+            1,
+            format.hash_version(),
+            chunks.len() as u8,
+            base_graphs.len() as u8,
+        ]);
+        let mut data = chunk_file(&header, &chunks);
+        let checksum = format.digest(&data);
+        data.extend_from_slice(checksum.as_bytes());
+        test_repo.write(
+            format!("objects/info/commit-graphs/graph-{}.graph", checksum),
+            data,
+        );
+        checksum
+    }
+
+    // Writes a chain of a root commit in the bottom file
+    // and its child in the top file, returning the graph and both hashes
+    fn two_file_chain(test_repo: &TestRepo, top_has_dates: bool) -> (CommitGraph, Hash, Hash) {
+        let root = FORMAT.digest(b"root");
+        let child = FORMAT.digest(b"child");
+        let bottom = write_graph_file(
+            test_repo,
+            &[TestCommit {
+                hash: root,
+                parent: NO_PARENT,
+                time: 1_700_000_000,
+                level: 1,
+                date_offset: 0,
+            }],
+            &[],
+            true,
+            &[],
+        );
+        let top = write_graph_file(
+            test_repo,
+            &[TestCommit {
+                hash: child,
+                parent: 0,
+                time: 1_700_000_100,
+                level: 2,
+                date_offset: 5,
+            }],
+            &[bottom],
+            top_has_dates,
+            &[],
+        );
+        test_repo.write(
+            "objects/info/commit-graphs/commit-graph-chain",
+            format!("{}\n{}\n", bottom, top),
+        );
+        let graphs_dir = test_repo.git_dir().join("objects/info/commit-graphs");
+        (
+            CommitGraph::open_chain(&graphs_dir, FORMAT).unwrap(),
+            root,
+            child,
+        )
+    }
+
+    #[test]
+    fn chain_with_dates_in_every_file_uses_corrected_dates() {
+        let test_repo = TestRepo::new();
+        let (graph, root, child) = two_file_chain(&test_repo, true);
+        assert_eq!(graph.get(&root).unwrap().unwrap().generation, 1_700_000_000);
+        let child = graph.get(&child).unwrap().unwrap();
+        assert_eq!(child.parents, vec![root]);
+        assert_eq!(child.generation, 1_700_000_105);
+    }
+
+    #[test]
+    fn chain_missing_dates_in_one_file_uses_levels() {
+        let test_repo = TestRepo::new();
+        let (graph, root, child) = two_file_chain(&test_repo, false);
+        assert_eq!(graph.get(&root).unwrap().unwrap().generation, 1);
+        assert_eq!(graph.get(&child).unwrap().unwrap().generation, 2);
+    }
+
+    #[test]
+    fn generation_overflow_past_the_end_of_time_is_corrupt() {
+        let test_repo = TestRepo::new();
+        let root = FORMAT.digest(b"root");
+        let checksum = write_graph_file(
+            &test_repo,
+            &[TestCommit {
+                hash: root,
+                parent: NO_PARENT,
+                time: 1_700_000_000,
+                level: 1,
+                date_offset: GENERATION_OVERFLOW_FLAG,
+            }],
+            &[],
+            true,
+            &[u64::MAX],
+        );
+        let path = test_repo.git_dir().join(format!(
+            "objects/info/commit-graphs/graph-{}.graph",
+            checksum
+        ));
+        let graph = CommitGraph::open(&path, FORMAT).unwrap();
+        let error = graph.get(&root).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::CorruptCommitGraph {
+                path,
+                reason: "generation overflow is out of range".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn corrupt_graph_is_ignored_for_commit_objects() {
+        let test_repo = TestRepo::new();
+        let tree = FORMAT.digest(b"tree 0\0");
+        let commit = format!(
+            "tree {}\n\
+             author A U Thor <author@example.com> 1700000000 +0000\n\
+             committer C O Mitter <committer@example.com> 1700000001 +0000\n\
+             \n\
+             Message\n",
+            tree
+        );
+        let pack = test_repo.write_pack(&[(ObjectType::Commit, commit.as_bytes())]);
+        let (hash, _) = pack.objects[0];
+        // The header and part of the table of contents, then nothing
+        test_repo.write("objects/info/commit-graph", b"CGPH\x01\x01\x03\x00OIDF");
+        let repository = test_repo.open();
+
+        assert!(repository.commit_graph().is_none());
+        assert!(repository.commit_graph_error().is_some());
+        let graph_commit = repository.read_graph_commit(hash).unwrap();
+        assert_eq!(graph_commit.tree, tree);
+        assert!(graph_commit.parents.is_empty());
+        assert_eq!(graph_commit.commit_time, 1_700_000_001);
+        assert_eq!(graph_commit.generation, GENERATION_NUMBER_INFINITY);
+    }
+
+    #[test]
+    fn sha256_graph_holds_32_byte_hashes() {
+        let format = ObjectFormat::Sha256;
+        let test_repo = TestRepo::with_format(format);
+        let root = format.digest(b"root");
+        let checksum = write_graph_file(
+            &test_repo,
+            &[TestCommit {
+                hash: root,
+                parent: NO_PARENT,
+                time: 1_700_000_000,
+                level: 1,
+                date_offset: 0,
+            }],
+            &[],
+            true,
+            &[],
+        );
+        test_repo.write(
+            "objects/info/commit-graphs/commit-graph-chain",
+            format!("{}\n", checksum),
+        );
+
+        let repository = test_repo.open();
+        assert_eq!(repository.commit_graph_error().map(|e| e.to_string()), None);
+        let graph = repository.commit_graph().unwrap();
+        assert_eq!(graph.hash(0), Some(root));
+        let commit = graph.get(&root).unwrap().unwrap();
+        assert_eq!(commit.tree, format.digest(b"tree"));
+        assert_eq!(commit.generation, 1_700_000_000);
+    }
+}
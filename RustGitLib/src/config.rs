@@ -0,0 +1,150 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A single `key = value` line from a config file.
+// Section and key names are case-insensitive, so they are stored lowercased.
+#[derive(Debug, Clone)]
+struct ConfigEntry {
+    section: String,
+    key: String,
+    value: String,
+}
+
+/// The variables set in a repository's `config` file
+#[derive(Debug, Clone, Default)]
+pub struct Config(Vec<ConfigEntry>);
+
+impl Config {
+    /// Reads a config file, treating a missing file as an empty config
+    pub fn read(path: &Path) -> io::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Config::parse(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parses the contents of a config file.
+    /// Lines that are not understood are skipped.
+    pub fn parse(contents: &str) -> Config {
+        let mut entries = vec![];
+        let mut section = String::new();
+        for line in contents.lines() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = parse_section_header(header);
+                continue;
+            }
+
+            // A key without `=` is a boolean that is set to true
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                None => (line, "true".to_string()),
+            };
+            entries.push(ConfigEntry {
+                section: section.clone(),
+                key: key.to_ascii_lowercase(),
+                value,
+            });
+        }
+        Config(entries)
+    }
+
+    /// Looks up a variable by its dotted name, e.g. `core.bare`.
+    /// If a variable is set more than once, the last value wins.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (section, key) = name.rsplit_once('.')?;
+        self.0
+            .iter()
+            .rev()
+            .find(|entry| {
+                entry.section.eq_ignore_ascii_case(section) && entry.key.eq_ignore_ascii_case(key)
+            })
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// Looks up every value of a variable that may be set more than once,
+    /// e.g. `remote.origin.fetch`, in the order they are set
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        let Some((section, key)) = name.rsplit_once('.') else {
+            return vec![];
+        };
+        self.0
+            .iter()
+            .filter(|entry| {
+                entry.section.eq_ignore_ascii_case(section) && entry.key.eq_ignore_ascii_case(key)
+            })
+            .map(|entry| entry.value.as_str())
+            .collect()
+    }
+
+    /// Looks up a size in bytes, which may have a `k`, `m` or `g` suffix
+    pub fn get_size(&self, name: &str) -> Option<usize> {
+        let value = self.get(name)?.trim();
+        let (number, unit) = match value.char_indices().last()? {
+            (index, 'k' | 'K') => (&value[..index], 1 << 10),
+            (index, 'm' | 'M') => (&value[..index], 1 << 20),
+            (index, 'g' | 'G') => (&value[..index], 1 << 30),
+            _ => (value, 1),
+        };
+        number.parse::<usize>().ok()?.checked_mul(unit)
+    }
+
+    /// Looks up a boolean variable, accepting git's spellings of true and false
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name)?.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(true),
+            "false" | "no" | "off" | "0" | "" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+// Turns `core`, `remote "origin"` or `remote.origin` into a dotted section name.
+// Only the section part is case-insensitive; a quoted subsection keeps its case.
+fn parse_section_header(header: &str) -> String {
+    match header.split_once(' ') {
+        Some((section, subsection)) => {
+            let subsection = subsection.trim().trim_matches('"');
+            section.to_ascii_lowercase() + "." + subsection
+        }
+        _ => header.to_ascii_lowercase(),
+    }
+}
+
+// Removes a `#` or `;` comment that is not inside a quoted value
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Removes the quotes around a value and handles the common escapes
+fn unquote(value: &str) -> String {
+    let mut unquoted = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {}
+            '\\' => match chars.next() {
+                Some('n') => unquoted.push('\n'),
+                Some('t') => unquoted.push('\t'),
+                Some(c) => unquoted.push(c),
+                None => {}
+            },
+            _ => unquoted.push(c),
+        }
+    }
+    unquoted
+}
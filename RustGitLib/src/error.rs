@@ -0,0 +1,245 @@
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::abbrev::Ambiguous;
+use crate::hash::{Hash, HashPrefix};
+use crate::object::ObjectType;
+use crate::pack::verify::PackProblem;
+use crate::refs::RefError;
+
+/// The ways reading a repository can fail, other than plain I/O errors.
+/// Library functions return these inside an `io::Error`;
+/// use `RustGitError::from_io_error()` to get them back out and match on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RustGitError {
+    /// Neither this directory nor, if `searched_parents`, any above it is a repository
+    NotARepository {
+        path: PathBuf,
+        searched_parents: bool,
+    },
+    /// A `.git` file doesn't hold "gitdir: <path>"
+    InvalidGitFile(PathBuf),
+    /// `extensions.objectFormat` names a hash function this library doesn't know
+    UnknownObjectFormat(String),
+    /// No loose or packed object has this hash
+    ObjectNotFound(Hash),
+    /// An object's contents couldn't be parsed.
+    /// `offset` is where parsing stopped, counting from the start of the object's header.
+    CorruptObject {
+        hash: Hash,
+        reason: String,
+        offset: usize,
+    },
+    /// An object's contents hash to something other than the hash it was stored under
+    HashMismatch { expected: Hash, found: Hash },
+    /// An object's contents look like one half of a SHA-1 collision attack,
+    /// such as SHAttered, so it can't be trusted even though its hash matches
+    CollisionAttackDetected(Hash),
+    /// An object isn't of the type that was asked for,
+    /// e.g. a tree was read as a commit
+    UnexpectedType {
+        hash: Hash,
+        expected: ObjectType,
+        found: ObjectType,
+    },
+    /// No object's hash starts with this abbreviated hash
+    PrefixNotFound(HashPrefix),
+    /// A tree has no entry with this name
+    EntryNotFound { tree: Hash, name: String },
+    /// A string that should have been a hash isn't 40 or 64 hexadecimal characters
+    InvalidHash(String),
+    /// A ref couldn't be resolved
+    InvalidRef(RefError),
+    /// HEAD, or a branch asked for, is a branch with no commits yet,
+    /// as in a new repository
+    UnbornBranch(String),
+    /// The branch has no upstream branch configured
+    NoUpstream(String),
+    /// The upstream branch is on a remote whose fetch refspecs don't copy it
+    /// to a remote-tracking branch
+    UpstreamNotTracked(String),
+    /// An abbreviated hash matches more than one object
+    AmbiguousRevision(Ambiguous),
+    /// A revision expression doesn't name any object
+    UnknownRevision(String),
+    /// A revision expression can't be parsed
+    InvalidRevision { revision: String, reason: String },
+    /// A pack or its index is corrupt
+    PackError { path: PathBuf, problem: PackProblem },
+    /// The multi-pack-index is corrupt
+    CorruptMultiPackIndex(String),
+    /// A commit-graph file is corrupt
+    CorruptCommitGraph { path: PathBuf, reason: String },
+    /// A position past the end of the commit-graph
+    CommitPositionOutOfRange(usize),
+}
+
+impl RustGitError {
+    /// Finds the `RustGitError` inside an error returned by the library, if it has one
+    pub fn from_io_error(error: &Error) -> Option<&RustGitError> {
+        error.get_ref()?.downcast_ref()
+    }
+
+    /// The closest `io::ErrorKind`, used when wrapping this in an `io::Error`
+    pub fn kind(&self) -> ErrorKind {
+        use RustGitError::*;
+
+        match self {
+            NotARepository { .. }
+            | ObjectNotFound(_)
+            | PrefixNotFound(_)
+            | EntryNotFound { .. }
+            | UnbornBranch(_)
+            | NoUpstream(_)
+            | UpstreamNotTracked(_)
+            | UnknownRevision(_) => ErrorKind::NotFound,
+            InvalidRef(
+                RefError::NotFound(_) | RefError::Dangling { .. } | RefError::MissingFile(_),
+            ) => ErrorKind::NotFound,
+            UnexpectedType { .. }
+            | InvalidHash(_)
+            | AmbiguousRevision(_)
+            | InvalidRevision { .. }
+            | CommitPositionOutOfRange(_) => ErrorKind::InvalidInput,
+            UnknownObjectFormat(_) => ErrorKind::Unsupported,
+            _ => ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl Display for RustGitError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use RustGitError::*;
+
+        match self {
+            NotARepository {
+                path,
+                searched_parents: false,
+            } => write!(f, "Not a git repository: {}", path.display()),
+            NotARepository {
+                path,
+                searched_parents: true,
+            } => write!(
+                f,
+                "Not a git repository (or any of the parent directories): {}",
+                path.display()
+            ),
+            InvalidGitFile(path) => write!(f, "Invalid gitfile format: {}", path.display()),
+            UnknownObjectFormat(name) => write!(f, "Unknown object format: {}", name),
+            ObjectNotFound(hash) => write!(f, "Object {} not found", hash),
+            CorruptObject {
+                hash,
+                reason,
+                offset,
+            } => write!(
+                f,
+                "Malformed object {} at offset {}: {}",
+                hash, offset, reason
+            ),
+            HashMismatch { expected, found } => {
+                write!(f, "Object {} has wrong hash {}", expected, found)
+            }
+            CollisionAttackDetected(hash) => write!(
+                f,
+                "Object {} appears to be part of a SHA-1 collision attack",
+                hash
+            ),
+            UnexpectedType {
+                hash,
+                expected,
+                found,
+            } => write!(f, "Object {} is a {}, not a {}", hash, found, expected),
+            PrefixNotFound(prefix) => {
+                write!(f, "No object has a hash starting with {}", prefix)
+            }
+            EntryNotFound { tree, name } => write!(f, "No entry {} in tree {}", name, tree),
+            InvalidHash(hex_hash) => write!(f, "Invalid hash: {}", hex_hash),
+            InvalidRef(ref_error) => ref_error.fmt(f),
+            UnbornBranch(branch) => write!(f, "Branch {} has no commits yet", branch),
+            NoUpstream(branch) => write!(f, "No upstream configured for branch {}", branch),
+            UpstreamNotTracked(merge) => write!(
+                f,
+                "Upstream branch {} is not stored as a remote-tracking branch",
+                merge
+            ),
+            AmbiguousRevision(ambiguous) => ambiguous.fmt(f),
+            UnknownRevision(revision) => write!(f, "Unknown revision: {}", revision),
+            InvalidRevision { revision, reason } => {
+                write!(f, "Invalid revision {}: {}", revision, reason)
+            }
+            PackError { path, problem } => {
+                write!(f, "Corrupt pack {}: {}", path.display(), problem)
+            }
+            CorruptMultiPackIndex(reason) => write!(f, "Corrupt multi-pack-index: {}", reason),
+            CorruptCommitGraph { path, reason } => {
+                write!(f, "Corrupt commit-graph {}: {}", path.display(), reason)
+            }
+            CommitPositionOutOfRange(position) => {
+                write!(f, "Commit position {} is out of range", position)
+            }
+        }
+    }
+}
+
+impl error::Error for RustGitError {}
+
+impl From<RustGitError> for Error {
+    fn from(rustgit_error: RustGitError) -> Error {
+        Error::new(rustgit_error.kind(), rustgit_error)
+    }
+}
+
+impl From<RefError> for RustGitError {
+    fn from(ref_error: RefError) -> RustGitError {
+        RustGitError::InvalidRef(ref_error)
+    }
+}
+
+impl From<Ambiguous> for RustGitError {
+    fn from(ambiguous: Ambiguous) -> RustGitError {
+        RustGitError::AmbiguousRevision(ambiguous)
+    }
+}
+
+/// Why an object's contents couldn't be parsed, and how far parsing got.
+/// Reading the object by hash turns this into `RustGitError::CorruptObject`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub reason: String,
+    /// The offset from the start of the object's header
+    pub offset: usize,
+}
+
+impl ParseError {
+    pub fn new(offset: usize, reason: impl Into<String>) -> ParseError {
+        ParseError {
+            reason: reason.into(),
+            offset,
+        }
+    }
+
+    /// Attaches the hash of the object that failed to parse
+    pub fn in_object(self, hash: Hash) -> RustGitError {
+        RustGitError::CorruptObject {
+            hash,
+            reason: self.reason,
+            offset: self.offset,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.reason, self.offset)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for Error {
+    fn from(parse_error: ParseError) -> Error {
+        Error::new(ErrorKind::InvalidData, parse_error)
+    }
+}
@@ -0,0 +1,425 @@
+use sha1_checked::{CollisionResult, Sha1};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, Error, Write};
+use std::str::FromStr;
+
+use crate::error::RustGitError;
+
+/// The length of the longest hash, a SHA-256
+pub const MAX_HASH_BYTES: usize = 32;
+
+/// The hash function a repository names its objects with.
+/// Repositories made with `git init --object-format=sha256` use SHA-256;
+/// all others use SHA-1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ObjectFormat {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    /// The name used for `extensions.objectFormat` in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ObjectFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha1" => Some(ObjectFormat::Sha1),
+            "sha256" => Some(ObjectFormat::Sha256),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes in a hash
+    pub fn hash_bytes(self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+
+    /// The number of hexadecimal characters in a hash
+    pub fn hex_len(self) -> usize {
+        self.hash_bytes() * 2
+    }
+
+    /// The number identifying the hash function in the headers of
+    /// multi-pack-index and commit-graph files
+    pub fn hash_version(self) -> u8 {
+        match self {
+            ObjectFormat::Sha1 => 1,
+            ObjectFormat::Sha256 => 2,
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            // Return the real SHA-1 of colliding data, rather than
+            // the "safe" hash that SHA-1DC can substitute for it,
+            // so the hash still matches the object's name
+            ObjectFormat::Sha1 => Hasher::Sha1(Box::new(Sha1::builder().safe_hash(false).build())),
+            ObjectFormat::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Hashes some data all at once
+    pub fn digest(self, data: &[u8]) -> Hash {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Hashes some data all at once, failing with `CollisionAttackDetected`
+    /// if it looks like part of a SHA-1 collision attack
+    pub fn try_digest(self, data: &[u8]) -> Result<Hash, RustGitError> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.try_finalize()
+    }
+}
+
+impl Display for ObjectFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Computes a hash with either of the hash functions git uses.
+/// Data can be added with `update()` or written with `io::Write`.
+/// Like git, SHA-1 is computed with collision detection (SHA-1DC),
+/// which spots the data crafted by attacks such as SHAttered.
+#[derive(Clone)]
+pub enum Hasher {
+    // The collision detection state is large, so keep it off the stack
+    Sha1(Box<Sha1>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finishes the hash, ignoring whether a collision attack was detected
+    pub fn finalize(self) -> Hash {
+        match self {
+            Hasher::Sha1(hasher) => Hash::new(ObjectFormat::Sha1, hasher.try_finalize().hash()),
+            Hasher::Sha256(hasher) => Hash::new(ObjectFormat::Sha256, &hasher.finalize()),
+        }
+    }
+
+    /// Finishes the hash, failing with `CollisionAttackDetected`
+    /// if the data looks like part of a SHA-1 collision attack.
+    /// SHA-256 has no known collisions, so it always succeeds.
+    pub fn try_finalize(self) -> Result<Hash, RustGitError> {
+        match self {
+            Hasher::Sha1(hasher) => match hasher.try_finalize() {
+                CollisionResult::Ok(hash) => Ok(Hash::new(ObjectFormat::Sha1, &hash)),
+                CollisionResult::Mitigated(hash) | CollisionResult::Collision(hash) => Err(
+                    RustGitError::CollisionAttackDetected(Hash::new(ObjectFormat::Sha1, &hash)),
+                ),
+            },
+            Hasher::Sha256(hasher) => Ok(Hash::new(ObjectFormat::Sha256, &hasher.finalize())),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A hash identifies an object by the hash of its contents.
+// It is 20 bytes for SHA-1 repositories and 32 bytes for SHA-256 ones;
+// shorter hashes are padded with zeros so all hashes can be compared and copied alike.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash {
+    bytes: [u8; MAX_HASH_BYTES],
+    format: ObjectFormat,
+}
+
+impl Hash {
+    // `bytes` must be `format.hash_bytes()` long
+    pub(crate) fn new(format: ObjectFormat, bytes: &[u8]) -> Hash {
+        let mut padded = [0; MAX_HASH_BYTES];
+        padded[..format.hash_bytes()].copy_from_slice(bytes);
+        Hash {
+            bytes: padded,
+            format,
+        }
+    }
+
+    /// Makes a hash from its bytes, which must be the length
+    /// of a SHA-1 or SHA-256 hash
+    pub fn from_bytes(bytes: &[u8]) -> Option<Hash> {
+        let format = [ObjectFormat::Sha1, ObjectFormat::Sha256]
+            .into_iter()
+            .find(|format| format.hash_bytes() == bytes.len())?;
+        Some(Hash::new(format, bytes))
+    }
+
+    /// Reads a hash of the given format from the start of some bytes,
+    /// returning None if there aren't enough bytes
+    pub fn from_prefix(format: ObjectFormat, bytes: &[u8]) -> Option<Hash> {
+        Some(Hash::new(format, bytes.get(..format.hash_bytes())?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.format.hash_bytes()]
+    }
+
+    /// The hash function that made this hash
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    /// The first byte picks the fan-out bucket and loose object directory
+    pub fn first_byte(&self) -> u8 {
+        self.bytes[0]
+    }
+}
+
+fn hex_char_value(hex_char: u8) -> Option<u8> {
+    match hex_char {
+        b'0'..=b'9' => Some(hex_char - b'0'),
+        b'a'..=b'f' => Some(hex_char - b'a' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a 40-character (SHA-1) or 64-character (SHA-256) hexadecimal string into a hash
+pub fn hex_to_hash(hex_hash: &[u8]) -> Option<Hash> {
+    const BITS_PER_CHAR: usize = 4;
+    const CHARS_PER_BYTE: usize = 8 / BITS_PER_CHAR;
+
+    let byte_chunks = hex_hash.chunks_exact(CHARS_PER_BYTE);
+    if !byte_chunks.remainder().is_empty() {
+        return None;
+    }
+
+    let bytes = byte_chunks
+        .map(|hex_digits| {
+            hex_digits.iter().try_fold(0, |value, &byte| {
+                let char_value = hex_char_value(byte)?;
+                Some((value << BITS_PER_CHAR) | char_value)
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Hash::from_bytes(&bytes)
+}
+
+impl FromStr for Hash {
+    type Err = Error;
+    fn from_str(hex_hash: &str) -> io::Result<Self> {
+        hex_to_hash(hex_hash.as_bytes())
+            .ok_or_else(|| RustGitError::InvalidHash(hex_hash.to_string()).into())
+    }
+}
+
+impl Display for Hash {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Turn the hash back into a hexadecimal string
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Hash {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Hash({})", self)
+    }
+}
+
+/// Git won't look up an abbreviated hash shorter than this
+pub const MIN_ABBREV_CHARS: usize = 4;
+
+/// The first few hexadecimal characters of a hash,
+/// as given by someone using an abbreviated hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashPrefix(String);
+
+impl HashPrefix {
+    /// Parses an abbreviated hash of `MIN_ABBREV_CHARS` hexadecimal characters
+    /// up to the length of a full SHA-256 hash
+    pub fn parse(hex_prefix: &str) -> Option<HashPrefix> {
+        let valid = (MIN_ABBREV_CHARS..=MAX_HASH_BYTES * 2).contains(&hex_prefix.len())
+            && hex_prefix.bytes().all(|byte| byte.is_ascii_hexdigit());
+        valid.then(|| HashPrefix(hex_prefix.to_ascii_lowercase()))
+    }
+
+    /// The number of hexadecimal characters
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The first 2 characters as a byte, which picks the fan-out bucket
+    /// and loose object directory to search
+    pub fn first_byte(&self) -> u8 {
+        // The prefix was checked to be at least 4 hexadecimal characters when it was parsed
+        u8::from_str_radix(&self.0[..2], 16).unwrap()
+    }
+
+    /// The smallest hash of a format starting with this prefix,
+    /// or None if the prefix is longer than the format's hashes
+    pub fn lower_bound(&self, format: ObjectFormat) -> Option<Hash> {
+        let padded = format!("{:0<width$}", self.0, width = format.hex_len());
+        hex_to_hash(padded.as_bytes()).filter(|hash| hash.format() == format)
+    }
+
+    pub fn matches(&self, hash: &Hash) -> bool {
+        hash.to_string().starts_with(&self.0)
+    }
+}
+
+impl Display for HashPrefix {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The number of leading hexadecimal characters two hashes have in common
+pub fn common_prefix_chars(hash1: &Hash, hash2: &Hash) -> usize {
+    let mut chars = 0;
+    for (byte1, byte2) in hash1.as_bytes().iter().zip(hash2.as_bytes()) {
+        if byte1 == byte2 {
+            chars += 2;
+        } else {
+            // The high 4 bits are the first character of the byte
+            if byte1 >> 4 == byte2 >> 4 {
+                chars += 1;
+            }
+            break;
+        }
+    }
+    chars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::test_repo::TestRepo;
+
+    const BLOB1: &[u8] = b"first blob\n";
+    const BLOB2: &[u8] = b"second blob\n";
+
+    // The first 320 bytes of shattered-1.pdf from the SHAttered attack
+    // (https://shattered.io), as hexadecimal. They hash the same as
+    // the start of shattered-2.pdf, which differs in 62 bytes.
+    const SHATTERED_PREFIX: &str = concat!(
+        "255044462d312e330a25e2e3cfd30a0a0a312030206f626a0a3c3c2f57696474",
+        "682032203020522f4865696768742033203020522f547970652034203020522f",
+        "537562747970652035203020522f46696c7465722036203020522f436f6c6f72",
+        "53706163652037203020522f4c656e6774682038203020522f42697473506572",
+        "436f6d706f6e656e7420383e3e0a73747265616d0affd8fffe00245348412d31",
+        "20697320646561642121212121852fec092339759c39b1a1c63c4c97e1fffe01",
+        "7346dc9166b67e118f029ab621b2560ff9ca67cca8c7f85ba84c79030c2b3de2",
+        "18f86db3a90901d5df45c14f26fedfb3dc38e96ac22fe7bd728f0e45bce046d2",
+        "3c570feb141398bb552ef5a0a82be331fea48037b8b5d71f0e332edf93ac3500",
+        "eb4ddc0decc1a864790c782c76215660dd309791d06bd0af3f98cda4bc4629b1",
+    );
+
+    #[test]
+    fn formats_have_names_and_sizes() {
+        for (format, name, hex_len, hash_version) in [
+            (ObjectFormat::Sha1, "sha1", 40, 1),
+            (ObjectFormat::Sha256, "sha256", 64, 2),
+        ] {
+            assert_eq!(format.name(), name);
+            assert_eq!(ObjectFormat::from_name(name), Some(format));
+            assert_eq!(
+                ObjectFormat::from_name(&name.to_ascii_uppercase()),
+                Some(format)
+            );
+            assert_eq!(format.hex_len(), hex_len);
+            assert_eq!(format.hash_version(), hash_version);
+        }
+        assert_eq!(ObjectFormat::from_name("md5"), None);
+    }
+
+    #[test]
+    fn hex_round_trips_for_both_formats() {
+        // The hashes git gives the empty blob
+        for hex_hash in [
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391",
+            "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813",
+        ] {
+            let hash: Hash = hex_hash.parse().unwrap();
+            assert_eq!(hash.to_string(), hex_hash);
+            assert_eq!(hash.format().hex_len(), hex_hash.len());
+            assert_eq!(hash.format().digest(b"blob 0\0"), hash);
+        }
+        assert!("e69de29b".parse::<Hash>().is_err());
+        assert!("E69DE29BB2D1D6434B8B29AE775AD8C2E48C5391"
+            .parse::<Hash>()
+            .is_err());
+    }
+
+    #[test]
+    fn sha256_repository_round_trips() {
+        let test_repo = TestRepo::with_format(ObjectFormat::Sha256);
+        let loose = test_repo.write_object(ObjectType::Blob, BLOB1);
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, BLOB2)]);
+        let packed = pack.objects[0].0;
+        let repository = test_repo.open();
+        assert_eq!(repository.object_format(), ObjectFormat::Sha256);
+
+        for (hash, contents) in [(loose, BLOB1), (packed, BLOB2)] {
+            assert_eq!(hash.format(), ObjectFormat::Sha256);
+            let hex_hash = hash.to_string();
+            assert_eq!(hex_hash.len(), 64);
+            assert_eq!(repository.rev_parse(&hex_hash).unwrap(), hash);
+            assert_eq!(
+                repository.read_object(hash).unwrap(),
+                [format!("blob {}\0", contents.len()).as_bytes(), contents].concat()
+            );
+        }
+
+        let packs = repository.packs().unwrap();
+        let index = packs[0].index();
+        assert_eq!(index.hashes(), [packed]);
+        assert_eq!(index.pack_checksum().format(), ObjectFormat::Sha256);
+        assert!(repository.verify_pack(packs[0]).unwrap().is_ok());
+    }
+
+    #[test]
+    fn shattered_prefix_is_detected_as_a_collision_attack() {
+        let data: Vec<u8> = (0..SHATTERED_PREFIX.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&SHATTERED_PREFIX[i..i + 2], 16).unwrap())
+            .collect();
+        let hash: Hash = "f92d74e3874587aaf443d1db961d4e26dde13e9c".parse().unwrap();
+        assert_eq!(
+            ObjectFormat::Sha1.try_digest(&data),
+            Err(RustGitError::CollisionAttackDetected(hash))
+        );
+        // The object's real name is still available
+        assert_eq!(ObjectFormat::Sha1.digest(&data), hash);
+        // Ordinary data isn't mistaken for an attack
+        assert_eq!(
+            ObjectFormat::Sha1.try_digest(b"blob 0\0"),
+            Ok(ObjectFormat::Sha1.digest(b"blob 0\0"))
+        );
+    }
+}
@@ -0,0 +1,23 @@
+// Code shared by the RustGit tools.
+// Each module covers one part of a git repository:
+// object hashes, the objects themselves, refs, packs and the commit-graph.
+// Failures specific to git are described by `error::RustGitError`.
+// `Repository` knows where all of these live on disk.
+
+pub mod abbrev;
+pub mod commit_graph;
+pub mod config;
+pub mod error;
+pub mod hash;
+pub mod object;
+pub mod pack;
+pub mod refs;
+pub mod repository;
+pub mod revision;
+
+pub use repository::Repository;
+
+mod chunk;
+mod parse;
+#[cfg(test)]
+mod test_repo;
@@ -0,0 +1,964 @@
+use encoding_rs::Encoding;
+use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::io::{self, ErrorKind};
+
+use crate::error::{ParseError, RustGitError};
+use crate::hash::{hex_to_hash, Hash, ObjectFormat};
+use crate::parse::{parse_decimal, split_once, Cursor};
+use crate::repository::Repository;
+
+pub mod reader;
+pub mod signature;
+
+pub use reader::ObjectReader;
+pub use signature::Signature;
+
+pub const COMMIT_HEADER: &[u8] = b"commit ";
+const TREE_LINE_PREFIX: &[u8] = b"tree ";
+const PARENT_LINE_PREFIX: &[u8] = b"parent ";
+const AUTHOR_LINE_PREFIX: &[u8] = b"author ";
+const COMMITTER_LINE_PREFIX: &[u8] = b"committer ";
+
+pub const TREE_HEADER: &[u8] = b"tree ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Directory,
+    File,
+    SymbolicLink,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeEntry {
+    pub mode: Mode,
+    pub name: String,
+    pub hash: Hash,
+}
+
+#[derive(Debug)]
+pub struct Tree(pub Vec<TreeEntry>);
+
+pub const BLOB_HEADER: &[u8] = b"blob ";
+
+#[derive(Debug)]
+pub struct Blob(pub Vec<u8>);
+
+pub const TAG_HEADER: &[u8] = b"tag ";
+const OBJECT_LINE_PREFIX: &[u8] = b"object ";
+const TYPE_LINE_PREFIX: &[u8] = b"type ";
+const TAG_LINE_PREFIX: &[u8] = b"tag ";
+const TAGGER_LINE_PREFIX: &[u8] = b"tagger ";
+
+// The lines that can start a signature at the end of a tag's message:
+// PGP signatures, old-style PGP ones, X.509 (gpgsm) ones and SSH ones
+const SIGNATURE_PREFIXES: [&[u8]; 4] = [
+    b"-----BEGIN PGP SIGNATURE-----",
+    b"-----BEGIN PGP MESSAGE-----",
+    b"-----BEGIN SIGNED MESSAGE-----",
+    b"-----BEGIN SSH SIGNATURE-----",
+];
+
+/// The four kinds of object git stores
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectType {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectType {
+    /// The type's name, as written in object headers
+    pub fn name(&self) -> &'static str {
+        use ObjectType::*;
+
+        match self {
+            Commit => "commit",
+            Tree => "tree",
+            Blob => "blob",
+            Tag => "tag",
+        }
+    }
+
+    /// The type with the given name, e.g. "commit", or None if there isn't one
+    pub fn from_name(name: &str) -> Option<ObjectType> {
+        use ObjectType::*;
+
+        [Commit, Tree, Blob, Tag]
+            .into_iter()
+            .find(|object_type| object_type.name() == name)
+    }
+
+    /// The start of an object header for this type, e.g. "commit "
+    pub fn header(&self) -> &'static [u8] {
+        use ObjectType::*;
+
+        match self {
+            Commit => COMMIT_HEADER,
+            Tree => TREE_HEADER,
+            Blob => BLOB_HEADER,
+            Tag => TAG_HEADER,
+        }
+    }
+}
+
+impl Display for ObjectType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub struct Commit {
+    pub tree: Hash,
+    pub parents: Vec<Hash>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// The headers after the committer, e.g. `encoding`, `mergetag` and `gpgsig`,
+    /// as (name, value) pairs in the order they are stored.
+    /// Git doesn't fix their order, so keeping it is what lets
+    /// the commit be written back byte for byte.
+    pub headers: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The message as stored, which includes the commit description.
+    /// It is in `encoding()` if there is one, usually UTF-8 otherwise,
+    /// but git doesn't check either; use `message_text()` to display it.
+    pub message: Vec<u8>,
+}
+
+// Byte strings in objects are debugged as text, rather than as lists of numbers
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+impl Debug for Commit {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Commit")
+            .field("tree", &self.tree)
+            .field("parents", &self.parents)
+            .field("author", &self.author)
+            .field("committer", &self.committer)
+            .field(
+                "headers",
+                &self
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (text(name), text(value)))
+                    .collect::<Vec<_>>(),
+            )
+            .field("message", &self.message_text())
+            .finish()
+    }
+}
+
+// Writes a header line, e.g. "tree <hash>".
+// Each line of a value after the first continues on a line starting with a space.
+// Like git, a header with an empty value is just its name, with no space after it.
+fn push_header(object: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    object.extend_from_slice(name);
+    if !value.is_empty() {
+        object.push(b' ');
+    }
+    for (i, line) in value.split(|&byte| byte == b'\n').enumerate() {
+        if i > 0 {
+            object.extend_from_slice(b"\n ");
+        }
+        object.extend_from_slice(line);
+    }
+    object.push(b'\n');
+}
+
+impl Commit {
+    /// The commit's contents as stored in its object, without the "commit <size>\0" header.
+    /// Headers are written in the order they were parsed, so a parsed commit
+    /// is written back byte for byte and keeps its hash.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut object = vec![];
+        push_header(&mut object, b"tree", self.tree.to_string().as_bytes());
+        for parent in &self.parents {
+            push_header(&mut object, b"parent", parent.to_string().as_bytes());
+        }
+        push_header(&mut object, b"author", &self.author.to_bytes());
+        push_header(&mut object, b"committer", &self.committer.to_bytes());
+        for (name, value) in &self.headers {
+            push_header(&mut object, name, value);
+        }
+        object.push(b'\n');
+        object.extend_from_slice(&self.message);
+        object
+    }
+
+    /// The message as text, for display. It is decoded from the commit's `encoding`
+    /// if it has one that is known, otherwise from UTF-8. Bytes that can't be
+    /// decoded are replaced with U+FFFD rather than making the commit unreadable.
+    pub fn message_text(&self) -> Cow<'_, str> {
+        let encoding = self
+            .header(b"encoding")
+            .and_then(|label| Encoding::for_label(label.trim_ascii()));
+        match encoding {
+            Some(encoding) => encoding.decode_without_bom_handling(&self.message).0,
+            None => String::from_utf8_lossy(&self.message),
+        }
+    }
+
+    /// The value of the first header with this name, if there is one.
+    /// Like git, any later headers with the same name are ignored.
+    pub fn header(&self, name: &[u8]) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// The character encoding of the message, from the `encoding` header.
+    /// Git only writes one when the message isn't UTF-8.
+    pub fn encoding(&self) -> Option<Cow<'_, str>> {
+        self.header(b"encoding").map(String::from_utf8_lossy)
+    }
+
+    /// The tag objects of any signed tags this commit merged,
+    /// from `mergetag` headers, so their signatures can be checked later
+    pub fn mergetags(&self) -> impl Iterator<Item = &[u8]> {
+        self.headers
+            .iter()
+            .filter(|(name, _)| name == b"mergetag")
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// The signature over the rest of the commit, e.g. a PGP or SSH signature,
+    /// from the `gpgsig` header
+    pub fn gpgsig(&self) -> Option<&[u8]> {
+        self.header(b"gpgsig")
+    }
+
+    /// The signature over the SHA-256 version of the commit,
+    /// from the `gpgsig-sha256` header
+    pub fn gpgsig_sha256(&self) -> Option<&[u8]> {
+        self.header(b"gpgsig-sha256")
+    }
+}
+
+/// An annotated tag: a name for another object, usually a commit,
+/// along with who made it and a message
+pub struct Tag {
+    /// The object the tag points to, which can be another tag
+    pub object: Hash,
+    /// The type of `object`, from the tag's `type` line
+    pub object_type: ObjectType,
+    /// The tag's name as stored, e.g. "v1.0". Git doesn't check that it is UTF-8,
+    /// or that it matches the ref the tag is under; use `tag_text()` to display it.
+    pub tag: Vec<u8>,
+    /// Who made the tag and when. Tags made by very old versions of git don't say.
+    pub tagger: Option<Signature>,
+    /// Any other headers after the tagger, as (name, value) pairs in order
+    pub extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The message as stored, without the signature
+    pub message: Vec<u8>,
+    /// For a signed tag, the PGP, X.509 or SSH signature
+    /// that git appends to the message
+    pub signature: Option<Vec<u8>>,
+}
+
+impl Debug for Tag {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Tag")
+            .field("object", &self.object)
+            .field("object_type", &self.object_type)
+            .field("tag", &self.tag_text())
+            .field("tagger", &self.tagger)
+            .field(
+                "extra_headers",
+                &self
+                    .extra_headers
+                    .iter()
+                    .map(|(name, value)| (text(name), text(value)))
+                    .collect::<Vec<_>>(),
+            )
+            .field("message", &self.message_text())
+            .field("signature", &self.signature.as_deref().map(text))
+            .finish()
+    }
+}
+
+impl Tag {
+    /// The tag's contents as stored in its object, without the "tag <size>\0" header
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut object = vec![];
+        push_header(&mut object, b"object", self.object.to_string().as_bytes());
+        push_header(&mut object, b"type", self.object_type.name().as_bytes());
+        push_header(&mut object, b"tag", &self.tag);
+        if let Some(tagger) = &self.tagger {
+            push_header(&mut object, b"tagger", &tagger.to_bytes());
+        }
+        for (name, value) in &self.extra_headers {
+            push_header(&mut object, name, value);
+        }
+        object.push(b'\n');
+        object.extend_from_slice(&self.message);
+        if let Some(signature) = &self.signature {
+            object.extend_from_slice(signature);
+        }
+        object
+    }
+
+    /// The tag's name as text, for display, with any bytes that aren't UTF-8 replaced
+    pub fn tag_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.tag)
+    }
+
+    /// The message as text, for display, with any bytes that aren't UTF-8 replaced
+    pub fn message_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.message)
+    }
+}
+
+/// An object of any type, for when the type isn't known until it is read
+#[derive(Debug)]
+pub enum Object {
+    Commit(Commit),
+    Tree(Tree),
+    Blob(Blob),
+    Tag(Tag),
+}
+
+impl Object {
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            Object::Commit(_) => ObjectType::Commit,
+            Object::Tree(_) => ObjectType::Tree,
+            Object::Blob(_) => ObjectType::Blob,
+            Object::Tag(_) => ObjectType::Tag,
+        }
+    }
+}
+
+/// Puts the header, e.g. "blob 12\0", in front of an object's contents.
+/// This is how objects are stored in loose files and how they are hashed.
+pub fn with_header(object_type: ObjectType, contents: &[u8]) -> Vec<u8> {
+    let mut object = object_type.header().to_vec();
+    object.extend_from_slice(contents.len().to_string().as_bytes());
+    object.push(b'\0');
+    object.extend_from_slice(contents);
+    object
+}
+
+/// Splits an object into its type and contents,
+/// checking that the size in the header is correct
+pub fn parse_header(object: &[u8]) -> Result<(ObjectType, &[u8]), ParseError> {
+    let object_type = header_type(object)?;
+    Ok((object_type, check_header(object, object_type.header())?))
+}
+
+/// Parses just an object's header, e.g. "blob 12\0", into its type and size,
+/// for when the contents haven't been read yet
+pub fn parse_header_only(header: &[u8]) -> Result<(ObjectType, usize), ParseError> {
+    let object_type = header_type(header)?;
+    let mut cursor = Cursor::new(header);
+    cursor.strip_prefix(object_type.header());
+    let size_offset = cursor.offset();
+    let size = cursor.take_until(b'\0', "header is not terminated")?;
+    let size =
+        parse_decimal(size).ok_or_else(|| ParseError::new(size_offset, "size is not a number"))?;
+    Ok((object_type, size))
+}
+
+// Finds the type an object's header starts with
+fn header_type(object: &[u8]) -> Result<ObjectType, ParseError> {
+    use ObjectType::*;
+
+    [Commit, Tree, Blob, Tag]
+        .into_iter()
+        .find(|object_type| object.starts_with(object_type.header()))
+        .ok_or_else(|| ParseError::new(0, "unknown object type"))
+}
+
+/// Checks that an object's header has the expected type, e.g. "commit ",
+/// and the object size is correct
+pub fn check_header<'a>(object: &'a [u8], header: &[u8]) -> Result<&'a [u8], ParseError> {
+    let mut cursor = Cursor::new(object);
+    take_header(&mut cursor, header)?;
+    Ok(cursor.rest())
+}
+
+// Reads past an object's header, checking its type and size
+fn take_header(cursor: &mut Cursor, header: &[u8]) -> Result<(), ParseError> {
+    if !cursor.strip_prefix(header) {
+        return Err(cursor.error(format!(
+            "expected a {:?} header",
+            String::from_utf8_lossy(header).trim_end()
+        )));
+    }
+    let size_offset = cursor.offset();
+    let size = cursor.take_until(b'\0', "header is not terminated")?;
+    let size =
+        parse_decimal(size).ok_or_else(|| ParseError::new(size_offset, "size is not a number"))?;
+    if cursor.rest().len() != size {
+        return Err(ParseError::new(
+            size_offset,
+            format!(
+                "header gives size {}, but the contents are {} bytes",
+                size,
+                cursor.rest().len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a tree object, whose entries hold hashes of the given format
+pub fn parse_tree(object: &[u8], format: ObjectFormat) -> Result<Tree, ParseError> {
+    let mut cursor = Cursor::new(object);
+    take_header(&mut cursor, TREE_HEADER)?;
+    let mut entries = vec![];
+    while !cursor.is_empty() {
+        let mode_offset = cursor.offset();
+        let mode = cursor.take_until(b' ', "entry mode is not terminated")?;
+        let mode = match mode {
+            b"40000" => Mode::Directory,
+            b"100644" => Mode::File,
+            b"100755" => Mode::File,
+            b"120000" => Mode::SymbolicLink,
+            _ => {
+                return Err(ParseError::new(
+                    mode_offset,
+                    format!("unknown entry mode {}", String::from_utf8_lossy(mode)),
+                ))
+            }
+        };
+
+        let name_offset = cursor.offset();
+        let name = cursor.take_until(b'\0', "entry name is not terminated")?;
+        let name = String::from_utf8(name.to_vec())
+            .map_err(|_| ParseError::new(name_offset, "entry name is not UTF-8"))?;
+
+        let hash = cursor.take(format.hash_bytes(), "entry hash is truncated")?;
+        let hash = Hash::new(format, hash);
+
+        entries.push(TreeEntry { mode, name, hash });
+    }
+    Ok(Tree(entries))
+}
+
+// Reads a header line, e.g. "tree <hash>", up to its newline
+fn take_line<'a>(cursor: &mut Cursor<'a>, name: &str) -> Result<&'a [u8], ParseError> {
+    cursor.take_until(b'\n', &format!("{} line is not terminated", name))
+}
+
+// Reads the hash on a header line, e.g. "tree <hash>"
+fn take_hash_line(
+    cursor: &mut Cursor,
+    name: &str,
+    format: ObjectFormat,
+) -> Result<Hash, ParseError> {
+    let hash_offset = cursor.offset();
+    let hash = take_line(cursor, name)?;
+    hex_to_hash(hash)
+        .filter(|hash| hash.format() == format)
+        .ok_or_else(|| ParseError::new(hash_offset, format!("invalid {} hash", name)))
+}
+
+// A header's name and value
+type Header<'a> = (&'a [u8], Vec<u8>);
+
+// Reads the next of the headers that can follow the fixed ones in a commit or tag,
+// returning its name and value, or None once the blank line ending the headers is read.
+// A value spanning several lines continues on lines starting with a space,
+// which are joined back together with newlines.
+fn take_extra_header<'a>(cursor: &mut Cursor<'a>) -> Result<Option<Header<'a>>, ParseError> {
+    if cursor.strip_prefix(b"\n") {
+        return Ok(None);
+    }
+    if cursor.is_empty() {
+        return Err(cursor.error("expected a blank line before the message"));
+    }
+    let line = take_line(cursor, "header")?;
+    let (name, value) = split_once(line, b' ').unwrap_or((line, &[]));
+    let mut value = value.to_vec();
+    while cursor.strip_prefix(b" ") {
+        value.push(b'\n');
+        value.extend_from_slice(take_line(cursor, "header")?);
+    }
+    Ok(Some((name, value)))
+}
+
+/// Parses a commit object, whose tree and parents are hashes of the given format
+pub fn parse_commit(object: &[u8], format: ObjectFormat) -> Result<Commit, ParseError> {
+    let mut cursor = Cursor::new(object);
+    take_header(&mut cursor, COMMIT_HEADER)?;
+
+    if !cursor.strip_prefix(TREE_LINE_PREFIX) {
+        return Err(cursor.error("expected a tree line"));
+    }
+    let tree = take_hash_line(&mut cursor, "tree", format)?;
+
+    let mut parents = vec![];
+    while cursor.strip_prefix(PARENT_LINE_PREFIX) {
+        parents.push(take_hash_line(&mut cursor, "parent", format)?);
+    }
+
+    if !cursor.strip_prefix(AUTHOR_LINE_PREFIX) {
+        return Err(cursor.error("expected an author line"));
+    }
+    let author = Signature::parse(take_line(&mut cursor, "author")?);
+
+    if !cursor.strip_prefix(COMMITTER_LINE_PREFIX) {
+        return Err(cursor.error("expected a committer line"));
+    }
+    let committer = Signature::parse(take_line(&mut cursor, "committer")?);
+
+    let mut headers = vec![];
+    while let Some((name, value)) = take_extra_header(&mut cursor)? {
+        headers.push((name.to_vec(), value));
+    }
+    let message = cursor.rest().to_vec();
+
+    Ok(Commit {
+        tree,
+        parents,
+        author,
+        committer,
+        headers,
+        message,
+    })
+}
+
+// Splits a tag's message from the signature appended to it, if it is signed.
+// Like git, the signature starts at the last line that looks like the start of one.
+fn split_signature(message: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let signature_start = (0..message.len())
+        .rev()
+        .filter(|&start| start == 0 || message[start - 1] == b'\n')
+        .find(|&start| {
+            SIGNATURE_PREFIXES
+                .iter()
+                .any(|prefix| message[start..].starts_with(prefix))
+        });
+    match signature_start {
+        Some(start) => (&message[..start], Some(&message[start..])),
+        None => (message, None),
+    }
+}
+
+/// Parses a tag object, which points to a hash of the given format
+pub fn parse_tag(object: &[u8], format: ObjectFormat) -> Result<Tag, ParseError> {
+    let mut cursor = Cursor::new(object);
+    take_header(&mut cursor, TAG_HEADER)?;
+
+    if !cursor.strip_prefix(OBJECT_LINE_PREFIX) {
+        return Err(cursor.error("expected an object line"));
+    }
+    let object = take_hash_line(&mut cursor, "object", format)?;
+
+    if !cursor.strip_prefix(TYPE_LINE_PREFIX) {
+        return Err(cursor.error("expected a type line"));
+    }
+    let type_offset = cursor.offset();
+    let type_name = take_line(&mut cursor, "type")?;
+    let object_type = std::str::from_utf8(type_name)
+        .ok()
+        .and_then(ObjectType::from_name)
+        .ok_or_else(|| {
+            ParseError::new(
+                type_offset,
+                format!("unknown object type {}", String::from_utf8_lossy(type_name)),
+            )
+        })?;
+
+    if !cursor.strip_prefix(TAG_LINE_PREFIX) {
+        return Err(cursor.error("expected a tag line"));
+    }
+    let tag = take_line(&mut cursor, "tag")?.to_vec();
+
+    let tagger = if cursor.strip_prefix(TAGGER_LINE_PREFIX) {
+        Some(Signature::parse(take_line(&mut cursor, "tagger")?))
+    } else {
+        None
+    };
+
+    let mut extra_headers = vec![];
+    while let Some((name, value)) = take_extra_header(&mut cursor)? {
+        extra_headers.push((name.to_vec(), value));
+    }
+    let (message, signature) = split_signature(cursor.rest());
+
+    Ok(Tag {
+        object,
+        object_type,
+        tag,
+        tagger,
+        extra_headers,
+        message: message.to_vec(),
+        signature: signature.map(<[u8]>::to_vec),
+    })
+}
+
+/// Parses an object of whichever type its header gives.
+/// Hashes in commits, trees and tags are of the given format.
+pub fn parse_object(object: &[u8], format: ObjectFormat) -> Result<Object, ParseError> {
+    Ok(match header_type(object)? {
+        ObjectType::Commit => Object::Commit(parse_commit(object, format)?),
+        ObjectType::Tree => Object::Tree(parse_tree(object, format)?),
+        ObjectType::Blob => Object::Blob(Blob(check_header(object, BLOB_HEADER)?.to_vec())),
+        ObjectType::Tag => Object::Tag(parse_tag(object, format)?),
+    })
+}
+
+impl Repository {
+    /// Read the byte contents of an object, including its header.
+    /// Loose objects are looked for first, then packed ones.
+    pub fn read_object(&self, hash: Hash) -> io::Result<Vec<u8>> {
+        let contents = match self.read_loose_object(hash)? {
+            Some(contents) => contents,
+            None => match self.read_packed_object(hash)? {
+                Some((object_type, contents)) => with_header(object_type, &contents),
+                None => return Err(RustGitError::ObjectNotFound(hash).into()),
+            },
+        };
+        // Like git, refuse objects that look crafted to collide with another
+        let contents_hash = self.object_format().try_digest(&contents)?;
+        if contents_hash != hash {
+            return Err(RustGitError::HashMismatch {
+                expected: hash,
+                found: contents_hash,
+            }
+            .into());
+        }
+        Ok(contents)
+    }
+
+    /// Reads an object and splits it into its type and contents
+    pub fn read_object_contents(&self, hash: Hash) -> io::Result<(ObjectType, Vec<u8>)> {
+        let object = self.read_object(hash)?;
+        let (object_type, contents) = parse_header(&object).map_err(|e| e.in_object(hash))?;
+        Ok((object_type, contents.to_vec()))
+    }
+
+    // Opens an object's own file under `objects`,
+    // returning None if there is no such file
+    fn open_loose_object(&self, hash: Hash) -> io::Result<Option<File>> {
+        // The first 2 characters of the hexadecimal hash form the directory;
+        // the rest forms the filename
+        let hex_hash = hash.to_string();
+        let (directory_name, file_name) = hex_hash.split_at(2);
+        let object_file = self.objects_dir().join(directory_name).join(file_name);
+        match File::open(object_file) {
+            Ok(object_file) => Ok(Some(object_file)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Reads an object from its own file under `objects`,
+    // returning None if there is no such file
+    fn read_loose_object(&self, hash: Hash) -> io::Result<Option<Vec<u8>>> {
+        let Some(object_file) = self.open_loose_object(hash)? else {
+            return Ok(None);
+        };
+        let mut contents = vec![];
+        ZlibDecoder::new(object_file).read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+
+    // Reads an object, including its header, failing with `UnexpectedType`
+    // if it isn't of the expected type
+    fn read_object_of_type(&self, hash: Hash, expected: ObjectType) -> io::Result<Vec<u8>> {
+        let object = self.read_object(hash)?;
+        let found = header_type(&object).map_err(|e| e.in_object(hash))?;
+        if found != expected {
+            return Err(RustGitError::UnexpectedType {
+                hash,
+                expected,
+                found,
+            }
+            .into());
+        }
+        Ok(object)
+    }
+
+    /// Reads and parses an object of any type
+    pub fn read_any_object(&self, hash: Hash) -> io::Result<Object> {
+        let object = self.read_object(hash)?;
+        Ok(parse_object(&object, self.object_format()).map_err(|e| e.in_object(hash))?)
+    }
+
+    pub fn read_tree(&self, hash: Hash) -> io::Result<Tree> {
+        let object = self.read_object_of_type(hash, ObjectType::Tree)?;
+        Ok(parse_tree(&object, self.object_format()).map_err(|e| e.in_object(hash))?)
+    }
+
+    pub fn read_blob(&self, hash: Hash) -> io::Result<Blob> {
+        let object = self.read_object_of_type(hash, ObjectType::Blob)?;
+        let bytes = check_header(&object, BLOB_HEADER).map_err(|e| e.in_object(hash))?;
+        Ok(Blob(bytes.to_vec()))
+    }
+
+    /// Finds the object at a slash-separated path under a tree.
+    /// An empty path is the tree itself.
+    pub fn find_path(&self, tree: Hash, path: &str) -> io::Result<Hash> {
+        let mut hash = tree;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let tree = self.read_tree(hash)?;
+            let entry = tree
+                .0
+                .iter()
+                .find(|entry| entry.name == name)
+                .ok_or_else(|| RustGitError::EntryNotFound {
+                    tree: hash,
+                    name: name.to_string(),
+                })?;
+            hash = entry.hash;
+        }
+        Ok(hash)
+    }
+
+    /// Finds the blob at a slash-separated path under a tree
+    pub fn get_file_blob(&self, tree: Hash, path: &str) -> io::Result<Blob> {
+        self.read_blob(self.find_path(tree, path)?)
+    }
+
+    pub fn read_commit(&self, hash: Hash) -> io::Result<Commit> {
+        let object = self.read_object_of_type(hash, ObjectType::Commit)?;
+        Ok(parse_commit(&object, self.object_format()).map_err(|e| e.in_object(hash))?)
+    }
+
+    pub fn read_tag(&self, hash: Hash) -> io::Result<Tag> {
+        let object = self.read_object_of_type(hash, ObjectType::Tag)?;
+        Ok(parse_tag(&object, self.object_format()).map_err(|e| e.in_object(hash))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    const TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+    fn commit_with_headers(headers: &str, message: &[u8]) -> Vec<u8> {
+        let mut contents = format!(
+            "tree {TREE}\n\
+             author A U Thor <author@example.com> 1700000000 +0100\n\
+             committer C O Mitter <committer@example.com> 1700000001 -0430\n\
+             {headers}\n"
+        )
+        .into_bytes();
+        contents.extend_from_slice(message);
+        contents
+    }
+
+    #[test]
+    fn headers_round_trip_in_any_order() {
+        // Not the order git writes them in, and with a header git doesn't know
+        let contents = commit_with_headers(
+            "gpgsig-sha256 -----BEGIN SSH SIGNATURE-----\n \
+             U1NIU0lH\n \
+             -----END SSH SIGNATURE-----\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n \
+             iQEz\n \
+             -----END PGP SIGNATURE-----\n\
+             x-foo bar\n\
+             mergetag object 5ac7665d2bd9c0f1b0a1d25a7e7a0e5d2c4a1b9f\n \
+             type commit\n\
+             encoding ISO-8859-1\n\
+             mergetag second\n\
+             x-empty\n",
+            b"Caf\xe9\n",
+        );
+        let commit = parse_commit(
+            &with_header(ObjectType::Commit, &contents),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+        assert_eq!(commit.to_bytes(), contents);
+
+        let names: Vec<_> = commit.headers.iter().map(|(name, _)| text(name)).collect();
+        assert_eq!(
+            names,
+            [
+                "gpgsig-sha256",
+                "gpgsig",
+                "x-foo",
+                "mergetag",
+                "encoding",
+                "mergetag",
+                "x-empty"
+            ]
+        );
+        assert_eq!(commit.header(b"x-foo"), Some(&b"bar"[..]));
+        assert_eq!(commit.encoding().as_deref(), Some("ISO-8859-1"));
+        assert_eq!(commit.message_text(), "Café\n");
+        assert_eq!(
+            commit.gpgsig(),
+            Some(&b"-----BEGIN PGP SIGNATURE-----\n\niQEz\n-----END PGP SIGNATURE-----"[..])
+        );
+        assert_eq!(
+            commit.gpgsig_sha256(),
+            Some(&b"-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n-----END SSH SIGNATURE-----"[..])
+        );
+        let mergetags: Vec<_> = commit.mergetags().collect();
+        assert_eq!(
+            mergetags,
+            [
+                &b"object 5ac7665d2bd9c0f1b0a1d25a7e7a0e5d2c4a1b9f\ntype commit"[..],
+                b"second"
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_without_extra_headers_round_trips() {
+        let contents = commit_with_headers("", b"Message\n");
+        let commit = parse_commit(
+            &with_header(ObjectType::Commit, &contents),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+        assert!(commit.headers.is_empty());
+        assert_eq!(commit.encoding(), None);
+        assert_eq!(commit.gpgsig(), None);
+        assert_eq!(commit.to_bytes(), contents);
+    }
+
+    #[test]
+    fn message_text_is_decoded_using_the_encoding_header() {
+        let parse = |headers: &str, message: &[u8]| {
+            let contents = commit_with_headers(headers, message);
+            parse_commit(
+                &with_header(ObjectType::Commit, &contents),
+                ObjectFormat::Sha1,
+            )
+            .unwrap()
+        };
+        // Latin-1 labels decode as windows-1252, like the WHATWG standard says
+        let latin1 = parse("encoding ISO-8859-1\n", b"Caf\xe9 \x80\n");
+        assert_eq!(latin1.message, b"Caf\xe9 \x80\n");
+        assert_eq!(latin1.message_text(), "Caf\u{e9} \u{20ac}\n");
+        // The label is matched ignoring case and surrounding spaces
+        let lowercase = parse("encoding  latin1 \n", b"Caf\xe9\n");
+        assert_eq!(lowercase.message_text(), "Caf\u{e9}\n");
+        // Without a known encoding the message is UTF-8,
+        // and bytes that aren't are replaced
+        let utf8 = parse("", "Caf\u{e9}\n".as_bytes());
+        assert_eq!(utf8.message_text(), "Caf\u{e9}\n");
+        let unknown = parse("encoding x-unknown\n", b"Caf\xe9\n");
+        assert_eq!(unknown.encoding().as_deref(), Some("x-unknown"));
+        assert_eq!(unknown.message_text(), "Caf\u{FFFD}\n");
+    }
+
+    #[test]
+    fn tag_name_that_is_not_utf8_round_trips() {
+        let contents = [
+            format!("object {TREE}\ntype tree\ntag ").as_bytes(),
+            b"caf\xe9\n",
+            b"tagger T Agger <tagger@example.com> 1700000000 +0000\n\nMessage\n",
+        ]
+        .concat();
+        let tag = parse_tag(&with_header(ObjectType::Tag, &contents), ObjectFormat::Sha1).unwrap();
+        assert_eq!(tag.tag, b"caf\xe9");
+        assert_eq!(tag.tag_text(), "caf\u{FFFD}");
+        assert_eq!(tag.to_bytes(), contents);
+    }
+    // A blob, a tree holding it, a commit of the tree and a tag of the commit,
+    // with the tag packed and the rest loose
+    fn write_objects(test_repo: &TestRepo) -> [Hash; 4] {
+        let blob = test_repo.write_object(ObjectType::Blob, b"contents\n");
+        let tree = test_repo.write_object(
+            ObjectType::Tree,
+            &[b"100644 file\0", blob.as_bytes()].concat(),
+        );
+        let commit_contents = commit_with_headers("", b"Message\n");
+        let commit_contents = String::from_utf8(commit_contents)
+            .unwrap()
+            .replace(TREE, &tree.to_string());
+        let commit = test_repo.write_object(ObjectType::Commit, commit_contents.as_bytes());
+        let tag_contents = format!(
+            "object {commit}\ntype commit\ntag v1.0\n\
+             tagger T Agger <tagger@example.com> 1700000000 +0000\n\nRelease\n"
+        );
+        let pack = test_repo.write_pack(&[(ObjectType::Tag, tag_contents.as_bytes())]);
+        [blob, tree, commit, pack.objects[0].0]
+    }
+
+    #[test]
+    fn read_any_object_parses_each_type() {
+        let test_repo = TestRepo::new();
+        let [blob, tree, commit, tag] = write_objects(&test_repo);
+        let repository = test_repo.open();
+
+        let Object::Blob(Blob(contents)) = repository.read_any_object(blob).unwrap() else {
+            panic!("expected a blob");
+        };
+        assert_eq!(contents, b"contents\n");
+
+        let Object::Tree(Tree(entries)) = repository.read_any_object(tree).unwrap() else {
+            panic!("expected a tree");
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "file");
+        assert_eq!(entries[0].hash, blob);
+
+        let Object::Commit(read_commit) = repository.read_any_object(commit).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(read_commit.tree, tree);
+        assert_eq!(read_commit.message, b"Message\n");
+
+        let Object::Tag(read_tag) = repository.read_any_object(tag).unwrap() else {
+            panic!("expected a tag");
+        };
+        assert_eq!(read_tag.object, commit);
+        assert_eq!(read_tag.object_type, ObjectType::Commit);
+        assert_eq!(read_tag.tag, b"v1.0");
+
+        for (hash, object_type) in [
+            (blob, ObjectType::Blob),
+            (tree, ObjectType::Tree),
+            (commit, ObjectType::Commit),
+            (tag, ObjectType::Tag),
+        ] {
+            let object = repository.read_any_object(hash).unwrap();
+            assert_eq!(object.object_type(), object_type);
+        }
+    }
+
+    #[test]
+    fn reading_the_wrong_type_is_unexpected_type() {
+        let test_repo = TestRepo::new();
+        let [blob, tree, commit, tag] = write_objects(&test_repo);
+        let repository = test_repo.open();
+
+        let unexpected = |error: io::Error| {
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+            match RustGitError::from_io_error(&error) {
+                Some(RustGitError::UnexpectedType {
+                    hash,
+                    expected,
+                    found,
+                }) => (*hash, *expected, *found),
+                _ => panic!("expected an unexpected type, got {:?}", error),
+            }
+        };
+        assert_eq!(
+            unexpected(repository.read_commit(blob).unwrap_err()),
+            (blob, ObjectType::Commit, ObjectType::Blob)
+        );
+        assert_eq!(
+            unexpected(repository.read_tree(commit).unwrap_err()),
+            (commit, ObjectType::Tree, ObjectType::Commit)
+        );
+        assert_eq!(
+            unexpected(repository.read_blob(tag).unwrap_err()),
+            (tag, ObjectType::Blob, ObjectType::Tag)
+        );
+        assert_eq!(
+            unexpected(repository.read_tag(tree).unwrap_err()),
+            (tree, ObjectType::Tag, ObjectType::Tree)
+        );
+        assert_eq!(
+            repository.read_commit(blob).unwrap_err().to_string(),
+            format!("Object {} is a blob, not a commit", blob)
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use flate2::read::ZlibDecoder;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+use super::{parse_header_only, ObjectType};
+use crate::error::{ParseError, RustGitError};
+use crate::hash::{Hash, Hasher};
+use crate::pack::packfile::EntryKind;
+use crate::repository::Repository;
+
+// The longest header is "commit " followed by a 20-digit size and a NUL byte
+const MAX_HEADER_BYTES: u64 = 32;
+
+/// Reads an object's contents as a stream, so large blobs never have to fit in memory.
+/// The header is read when the object is opened, so its type and size are known
+/// before any contents are read. The contents are hashed as they are read,
+/// and once they run out the hash is checked: a mismatch, or contents
+/// that don't match the size in the header, are returned as an error
+/// from the final `read()`.
+pub struct ObjectReader {
+    hash: Hash,
+    object_type: ObjectType,
+    size: u64,
+    // The length of the "type size\0" header, so errors can give offsets from its start
+    header_len: u64,
+    contents: Box<dyn Read>,
+    // None once the hash has been checked
+    hasher: Option<Hasher>,
+    bytes_read: u64,
+}
+
+impl ObjectReader {
+    fn new(
+        repository: &Repository,
+        hash: Hash,
+        object_type: ObjectType,
+        size: u64,
+        contents: Box<dyn Read>,
+    ) -> ObjectReader {
+        // The hash covers the header as well as the contents
+        let header = format!("{} {}\0", object_type, size);
+        let mut hasher = repository.object_format().hasher();
+        hasher.update(header.as_bytes());
+        ObjectReader {
+            hash,
+            object_type,
+            size,
+            header_len: header.len() as u64,
+            contents,
+            hasher: Some(hasher),
+            bytes_read: 0,
+        }
+    }
+
+    /// The hash the object was opened by
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        self.object_type
+    }
+
+    /// The size of the contents, from the header
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn corrupt(&self, reason: &str) -> io::Error {
+        let offset = (self.header_len + self.bytes_read) as usize;
+        ParseError::new(offset, reason).in_object(self.hash).into()
+    }
+
+    // Called once the contents have run out
+    fn finish(&mut self) -> io::Result<()> {
+        let Some(hasher) = self.hasher.take() else {
+            return Ok(());
+        };
+        if self.bytes_read < self.size {
+            return Err(self.corrupt("contents are shorter than the size in the header"));
+        }
+        let found = hasher.try_finalize()?;
+        if found != self.hash {
+            return Err(RustGitError::HashMismatch {
+                expected: self.hash,
+                found,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if buffer.is_empty() || self.hasher.is_none() {
+            return Ok(0);
+        }
+
+        let remaining = self.size - self.bytes_read;
+        if remaining == 0 {
+            // Anything after the size given in the header is corrupt
+            let mut extra = [0];
+            if self.contents.read(&mut extra)? != 0 {
+                self.hasher = None;
+                return Err(self.corrupt("contents are longer than the size in the header"));
+            }
+            self.finish()?;
+            return Ok(0);
+        }
+
+        let length = (buffer.len() as u64).min(remaining) as usize;
+        let read = self.contents.read(&mut buffer[..length])?;
+        if read == 0 {
+            self.finish()?;
+            return Ok(0);
+        }
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buffer[..read]);
+        }
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+// Inflates a loose object as far as the end of its header and parses it
+fn read_loose_header(contents: &mut impl BufRead, hash: Hash) -> io::Result<(ObjectType, u64)> {
+    let mut header = vec![];
+    contents
+        .by_ref()
+        .take(MAX_HEADER_BYTES)
+        .read_until(b'\0', &mut header)?;
+    let (object_type, size) = parse_header_only(&header).map_err(|e| e.in_object(hash))?;
+    Ok((object_type, size as u64))
+}
+
+impl Repository {
+    /// Reads just an object's type and size, like `git cat-file -t` and `-s`.
+    /// Loose objects are only inflated as far as the end of their header,
+    /// and packed objects not at all, except for the start of a delta.
+    /// Since the contents aren't read, the hash isn't checked.
+    pub fn read_object_header(&self, hash: Hash) -> io::Result<(ObjectType, u64)> {
+        if let Some(object_file) = self.open_loose_object(hash)? {
+            // With a buffer no bigger than a header, nothing past it is inflated
+            let mut contents =
+                BufReader::with_capacity(MAX_HEADER_BYTES as usize, ZlibDecoder::new(object_file));
+            return read_loose_header(&mut contents, hash);
+        }
+        match self.find_packed_object(hash)? {
+            Some((pack, offset)) => self.read_pack_entry_header(pack, offset),
+            None => Err(RustGitError::ObjectNotFound(hash).into()),
+        }
+    }
+
+    /// Opens an object for streaming its contents, reading just its header.
+    /// Loose objects are looked for first, then packed ones.
+    /// Packed objects stored as deltas have to be rebuilt in memory,
+    /// but all others are inflated as they are read.
+    pub fn open_object(&self, hash: Hash) -> io::Result<ObjectReader> {
+        if let Some(object_file) = self.open_loose_object(hash)? {
+            let mut contents = BufReader::new(ZlibDecoder::new(object_file));
+            let (object_type, size) = read_loose_header(&mut contents, hash)?;
+            return Ok(ObjectReader::new(
+                self,
+                hash,
+                object_type,
+                size,
+                Box::new(contents),
+            ));
+        }
+
+        let Some((pack, offset)) = self.find_packed_object(hash)? else {
+            return Err(RustGitError::ObjectNotFound(hash).into());
+        };
+        let mut file = pack.open_file()?;
+        let header = pack.read_entry_header(&mut file, offset)?;
+        let (object_type, size, contents): (_, _, Box<dyn Read>) = match header.kind {
+            EntryKind::Base(object_type) => (
+                object_type,
+                header.size,
+                Box::new(pack.stream_entry_data(file, &header)?),
+            ),
+            // A delta needs random access to its base, so resolve it all at once
+            EntryKind::OffsetDelta { .. } | EntryKind::RefDelta { .. } => {
+                let (object_type, contents) = self.read_pack_entry(pack, offset)?;
+                (
+                    object_type,
+                    contents.len() as u64,
+                    Box::new(Cursor::new(contents)),
+                )
+            }
+        };
+        Ok(ObjectReader::new(self, hash, object_type, size, contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::test_repo::{TestEntry, TestRepo};
+
+    const BASE: &[u8] = b"hello world";
+    // Copies all of BASE, then inserts "!"
+    const DELTA: &[u8] = b"\x0b\x0c\x90\x0b\x01!";
+    const RESULT: &[u8] = b"hello world!";
+    // Copies 6 bytes of BASE, inserts "there, ", then copies the other 5
+    const OTHER_DELTA: &[u8] = b"\x0b\x12\x90\x06\x07there, \x91\x06\x05";
+    const OTHER_RESULT: &[u8] = b"hello there, world";
+
+    fn hash(contents: &[u8]) -> Hash {
+        ObjectFormat::Sha1.digest(&crate::object::with_header(ObjectType::Blob, contents))
+    }
+
+    // Reads an object to the end, returning what was read before any error
+    fn read_all(repository: &Repository, hash: Hash) -> (Vec<u8>, io::Result<usize>) {
+        let mut reader = repository.open_object(hash).unwrap();
+        let mut contents = vec![];
+        let result = reader.read_to_end(&mut contents);
+        (contents, result)
+    }
+
+    fn corrupt_object(hash: Hash, reason: &str, offset: usize) -> RustGitError {
+        RustGitError::CorruptObject {
+            hash,
+            reason: reason.to_string(),
+            offset,
+        }
+    }
+
+    #[test]
+    fn streams_loose_and_packed_objects() {
+        let test_repo = TestRepo::new();
+        let loose = test_repo.write_object(ObjectType::Tree, b"");
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, BASE)]);
+        let repository = test_repo.open();
+
+        let reader = repository.open_object(loose).unwrap();
+        assert_eq!((reader.object_type(), reader.size()), (ObjectType::Tree, 0));
+        let (contents, result) = read_all(&repository, pack.objects[0].0);
+        assert_eq!(contents, BASE);
+        assert_eq!(result.unwrap(), BASE.len());
+    }
+
+    #[test]
+    fn contents_shorter_than_the_header() {
+        let test_repo = TestRepo::new();
+        let hash = hash(b"short");
+        test_repo.write_loose_object(hash, b"blob 20\0short");
+        let (contents, result) = read_all(&test_repo.open(), hash);
+        assert_eq!(contents, b"short");
+        let error = result.unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&corrupt_object(
+                hash,
+                "contents are shorter than the size in the header",
+                b"blob 20\0short".len()
+            ))
+        );
+    }
+
+    #[test]
+    fn contents_longer_than_the_header() {
+        let test_repo = TestRepo::new();
+        let hash = hash(b"long");
+        test_repo.write_loose_object(hash, b"blob 3\0long!");
+        let (contents, result) = read_all(&test_repo.open(), hash);
+        assert_eq!(contents, b"lon");
+        let error = result.unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&corrupt_object(
+                hash,
+                "contents are longer than the size in the header",
+                b"blob 3\0lon".len()
+            ))
+        );
+    }
+
+    #[test]
+    fn hash_is_checked_at_the_end() {
+        let test_repo = TestRepo::new();
+        // The contents of one blob stored under the hash of another
+        let expected = hash(b"expected");
+        test_repo.write_loose_object(expected, b"blob 5\0found");
+        let (contents, result) = read_all(&test_repo.open(), expected);
+        // Every byte is returned before the mismatch is found
+        assert_eq!(contents, b"found");
+        let error = result.unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::HashMismatch {
+                expected,
+                found: hash(b"found"),
+            })
+        );
+    }
+
+    #[test]
+    fn headers_of_deltas_give_the_final_object() {
+        let test_repo = TestRepo::new();
+        // A REF_DELTA may also be against an object outside the pack
+        let outside = test_repo.write_object(ObjectType::Blob, BASE);
+        test_repo.write_pack_entries(&[
+            TestEntry::Whole(ObjectType::Blob, b"unrelated", 9),
+            TestEntry::RefDelta {
+                base: outside,
+                delta: DELTA,
+                object: (ObjectType::Blob, RESULT),
+            },
+        ]);
+        // and an OFS_DELTA only against one inside it
+        let pack = test_repo.write_pack_entries(&[
+            TestEntry::Whole(ObjectType::Tree, b"", 0),
+            TestEntry::Whole(ObjectType::Blob, BASE, BASE.len() as u64),
+            TestEntry::OffsetDelta {
+                base: 1,
+                delta: OTHER_DELTA,
+                object: (ObjectType::Blob, OTHER_RESULT),
+            },
+        ]);
+        let repository = test_repo.open();
+
+        for (hash, contents) in [(hash(RESULT), RESULT), (pack.objects[2].0, OTHER_RESULT)] {
+            assert_eq!(
+                repository.read_object_header(hash).unwrap(),
+                (ObjectType::Blob, contents.len() as u64)
+            );
+            let (read, result) = read_all(&repository, hash);
+            assert_eq!(read, contents);
+            result.unwrap();
+        }
+    }
+}
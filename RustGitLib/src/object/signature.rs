@@ -0,0 +1,278 @@
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// Who made a commit or tag and when, from a line like
+/// "A U Thor <author@example.com> 1700000000 +0100".
+/// Git accepts lines that don't quite follow that form, so parsing never fails:
+/// a missing email is empty, a missing or unreadable date is time 0 in UTC,
+/// and a timestamp too big for 64 bits is clamped, as git does.
+/// The name and email are kept as bytes, since git doesn't require them to be UTF-8,
+/// and the original line is kept so it can be written back exactly,
+/// which keeps the object's hash the same.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    /// The timezone's offset from UTC, e.g. +0130 is 90
+    pub tz_offset_minutes: i32,
+    raw: Vec<u8>,
+}
+
+// The date after an email, or after the whole line if there is no email,
+// as its time and timezone offset. Returns None if there is no time.
+fn parse_date(date: &[u8]) -> Option<(u64, i32)> {
+    let date = date.trim_ascii_start();
+    let time_len = date.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    if time_len == 0 {
+        return None;
+    }
+    let (time, rest) = date.split_at(time_len);
+    // Like git's strtoumax(), clamp times that overflow
+    let time = time.iter().fold(0u64, |time, &digit| {
+        time.saturating_mul(10)
+            .saturating_add((digit - b'0') as u64)
+    });
+
+    // The timezone is a sign followed by hours and minutes, e.g. "-0430".
+    // Odd ones, like "+5" or "+123456", are read the same way git reads them:
+    // as a number whose last 2 digits are minutes.
+    let rest = rest.trim_ascii_start();
+    let (sign, digits) = match rest.split_first() {
+        Some((b'+', digits)) => (1, digits),
+        Some((b'-', digits)) => (-1, digits),
+        _ => return Some((time, 0)),
+    };
+    let tz = digits
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .try_fold(0i32, |tz, &digit| {
+            tz.checked_mul(10)?.checked_add((digit - b'0') as i32)
+        })
+        .unwrap_or(0);
+    Some((time, sign * (tz / 100 * 60 + tz % 100)))
+}
+
+// Splits a line without "<email>" into the name and the date after it,
+// which is a time, optionally followed by a timezone
+fn split_trailing_date(line: &[u8]) -> (&[u8], &[u8]) {
+    let is_number = |field: &[u8]| !field.is_empty() && field.iter().all(u8::is_ascii_digit);
+    let is_timezone = |field: &[u8]| matches!(field.split_first(), Some((b'+' | b'-', digits)) if is_number(digits));
+
+    // The space-separated fields and where each starts, last first
+    let mut fields = line
+        .split(|&byte| byte == b' ')
+        .scan(0, |start, field| {
+            let field_start = *start;
+            *start += field.len() + 1;
+            Some((field_start, field))
+        })
+        .filter(|(_, field)| !field.is_empty())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .peekable();
+    fields.next_if(|(_, field)| is_timezone(field));
+    match fields.next() {
+        Some((time_start, time)) if is_number(time) => line.split_at(time_start),
+        _ => (line, b""),
+    }
+}
+
+impl Signature {
+    /// Makes a signature in the form git writes, e.g.
+    /// "A U Thor <author@example.com> 1700000000 +0100"
+    pub fn new(
+        name: impl AsRef<[u8]>,
+        email: impl AsRef<[u8]>,
+        time: u64,
+        tz_offset_minutes: i32,
+    ) -> Signature {
+        let mut signature = Signature {
+            name: name.as_ref().to_vec(),
+            email: email.as_ref().to_vec(),
+            time,
+            tz_offset_minutes,
+            raw: vec![],
+        };
+        signature.raw = signature.canonical_bytes();
+        signature
+    }
+
+    /// Parses the rest of an "author" or "committer" line, after the first space
+    pub fn parse(line: &[u8]) -> Signature {
+        let email_start = line.iter().position(|&byte| byte == b'<');
+        let email_end = email_start.and_then(|start| {
+            line[start..]
+                .iter()
+                .position(|&byte| byte == b'>')
+                .map(|end| start + end)
+        });
+        let (name, email, date) = match (email_start, email_end) {
+            (Some(start), Some(end)) => {
+                // Like git, look for the date after the last '>',
+                // in case the email itself contains one
+                let date_start = line.iter().rposition(|&byte| byte == b'>').unwrap() + 1;
+                (&line[..start], &line[start + 1..end], &line[date_start..])
+            }
+            _ => {
+                let (name, date) = split_trailing_date(line);
+                (name, &b""[..], date)
+            }
+        };
+        let (time, tz_offset_minutes) = parse_date(date).unwrap_or((0, 0));
+        Signature {
+            name: name.trim_ascii().to_vec(),
+            email: email.to_vec(),
+            time,
+            tz_offset_minutes,
+            raw: line.to_vec(),
+        }
+    }
+
+    /// The name as text, for display, with any bytes that aren't UTF-8 replaced
+    pub fn name_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.name)
+    }
+
+    /// The email as text, for display, with any bytes that aren't UTF-8 replaced
+    pub fn email_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.email)
+    }
+
+    // The signature as git would write it
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let sign = if self.tz_offset_minutes < 0 { '-' } else { '+' };
+        let tz = self.tz_offset_minutes.unsigned_abs();
+        let mut bytes = self.name.clone();
+        bytes.extend_from_slice(b" <");
+        bytes.extend_from_slice(&self.email);
+        bytes.extend_from_slice(
+            format!("> {} {}{:02}{:02}", self.time, sign, tz / 60, tz % 60).as_bytes(),
+        );
+        bytes
+    }
+
+    /// The signature as it appears in an object.
+    /// This is exactly the bytes it was parsed from, unless its fields have been changed,
+    /// in which case it is written the way git would write it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let unchanged = Signature::parse(&self.raw);
+        let changed = unchanged.name != self.name
+            || unchanged.email != self.email
+            || unchanged.time != self.time
+            || unchanged.tz_offset_minutes != self.tz_offset_minutes;
+        if changed {
+            self.canonical_bytes()
+        } else {
+            self.raw.clone()
+        }
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+// The raw bytes are left out, since they repeat the other fields
+impl Debug for Signature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Signature")
+            .field("name", &self.name_text())
+            .field("email", &self.email_text())
+            .field("time", &self.time)
+            .field("tz_offset_minutes", &self.tz_offset_minutes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_signature_git_wrote() {
+        let signature = Signature::parse(b"A U Thor <author@example.com> 1700000000 +0130");
+        assert_eq!(signature.name, b"A U Thor");
+        assert_eq!(signature.email, b"author@example.com");
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.tz_offset_minutes, 90);
+    }
+
+    #[test]
+    fn negative_timezones_keep_their_minutes() {
+        let signature = Signature::parse(b"A U Thor <author@example.com> 1700000000 -0430");
+        assert_eq!(signature.tz_offset_minutes, -270);
+        let signature = Signature::parse(b"A U Thor <author@example.com> 1700000000 -0000");
+        assert_eq!(signature.tz_offset_minutes, 0);
+        assert_eq!(
+            Signature::new("A U Thor", "author@example.com", 1700000000, -270).to_bytes(),
+            b"A U Thor <author@example.com> 1700000000 -0430"
+        );
+    }
+
+    #[test]
+    fn missing_email_is_empty() {
+        let signature = Signature::parse(b"A U Thor 1700000000 -0100");
+        assert_eq!(signature.name, b"A U Thor");
+        assert_eq!(signature.email, b"");
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.tz_offset_minutes, -60);
+
+        // Without a date either, the whole line is the name
+        let signature = Signature::parse(b"A U Thor");
+        assert_eq!(signature.name, b"A U Thor");
+        assert_eq!(signature.email, b"");
+        assert_eq!(signature.time, 0);
+    }
+
+    #[test]
+    fn spaces_around_the_name_are_trimmed() {
+        let signature =
+            Signature::parse(b"  A  U Thor   <author@example.com>   1700000000   +0100");
+        assert_eq!(signature.name, b"A  U Thor");
+        assert_eq!(signature.email, b"author@example.com");
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.tz_offset_minutes, 60);
+    }
+
+    #[test]
+    fn unchanged_signatures_round_trip_exactly() {
+        for line in [
+            &b"A U Thor <author@example.com> 1700000000 +0100"[..],
+            b"  A  U Thor   <author@example.com>   1700000000   +0100",
+            b"A U Thor 1700000000 -0100",
+            b"A U Thor <author@example.com> 99999999999999999999999 +5",
+            b"A U Thor <author@example.com>",
+        ] {
+            assert_eq!(Signature::parse(line).to_bytes(), line);
+        }
+    }
+
+    #[test]
+    fn changed_signatures_are_written_like_git() {
+        let mut signature =
+            Signature::parse(b"  A  U Thor   <author@example.com>   1700000000 +0100");
+        signature.tz_offset_minutes = -150;
+        assert_eq!(
+            signature.to_bytes(),
+            b"A  U Thor <author@example.com> 1700000000 -0230"
+        );
+    }
+
+    #[test]
+    fn names_that_are_not_utf8_are_kept() {
+        let mut signature = Signature::parse(b"J\xf6rg <j\xf6rg@example.com> 1700000000 +0100");
+        assert_eq!(signature.name, b"J\xf6rg");
+        assert_eq!(signature.name_text(), "J\u{fffd}rg");
+        assert_eq!(signature.email_text(), "j\u{fffd}rg@example.com");
+        signature.time = 1700000001;
+        assert_eq!(
+            signature.to_bytes(),
+            b"J\xf6rg <j\xf6rg@example.com> 1700000001 +0100"
+        );
+    }
+}
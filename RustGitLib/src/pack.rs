@@ -0,0 +1,51 @@
+use std::io::{self, Read};
+
+use crate::hash::{Hash, ObjectFormat, MAX_HASH_BYTES};
+
+pub mod cache;
+pub mod delta;
+pub mod index;
+pub mod midx;
+pub mod packfile;
+pub mod verify;
+
+pub use cache::DeltaBaseCache;
+pub use index::PackIndex;
+pub use midx::MultiPackIndex;
+pub use packfile::Pack;
+
+/// The magic bytes at the start of a version 2 pack index
+pub const INDEX_MAGIC: [u8; 4] = *b"\xfftOc";
+
+// The most space reserved for an object before inflating it or applying a delta.
+// Sizes come from the pack, so a corrupt one could ask for far more memory
+// than there is; bigger objects grow their buffer as they are built.
+pub(crate) const MAX_RESERVED_ENTRY_BYTES: u64 = 16 << 20;
+
+/// Reads a fixed number of bytes from a stream.
+/// Rust's "const generics" make this function very useful.
+pub fn read_bytes<R: Read, const N: usize>(stream: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Reads a big-endian 32-bit (4-byte) integer from a stream
+pub fn read_u32<R: Read>(stream: &mut R) -> io::Result<u32> {
+    let bytes = read_bytes(stream)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// Reads a big-endian 64-bit (8-byte) integer from a stream
+pub fn read_u64<R: Read>(stream: &mut R) -> io::Result<u64> {
+    let bytes = read_bytes(stream)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Read an object hash of the given format from a stream
+pub fn read_hash<R: Read>(stream: &mut R, format: ObjectFormat) -> io::Result<Hash> {
+    let mut bytes = [0; MAX_HASH_BYTES];
+    let bytes = &mut bytes[..format.hash_bytes()];
+    stream.read_exact(bytes)?;
+    Ok(Hash::new(format, bytes))
+}
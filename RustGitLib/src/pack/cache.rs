@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::hash::Hash;
+use crate::object::ObjectType;
+
+/// The default memory limit, the same as git's `core.deltaBaseCacheLimit`
+pub const DEFAULT_DELTA_BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+/// Identifies an object in a pack: the pack's checksum,
+/// which is unique to its contents, and the object's offset in that pack
+pub type PackLocation = (Hash, u64);
+
+#[derive(Debug)]
+struct CacheEntry {
+    object_type: ObjectType,
+    contents: Rc<[u8]>,
+    // When the entry was last used, for finding the least recently used one
+    last_used: u64,
+}
+
+/// A cache of objects that were used as delta bases.
+/// Walking history reads many objects whose deltas share bases,
+/// so keeping resolved bases around saves inflating them again and again.
+/// When the cache goes over its memory limit, the least recently used
+/// entries are dropped.
+#[derive(Debug)]
+pub struct DeltaBaseCache {
+    limit: usize,
+    // The total size of all cached contents
+    used: usize,
+    entries: HashMap<PackLocation, CacheEntry>,
+    // The location of each entry, by when it was last used
+    usage: BTreeMap<u64, PackLocation>,
+    clock: u64,
+}
+
+impl DeltaBaseCache {
+    /// Creates an empty cache holding at most `limit` bytes of objects
+    pub fn new(limit: usize) -> DeltaBaseCache {
+        DeltaBaseCache {
+            limit,
+            used: 0,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Changes the memory limit, dropping entries if the cache is now too big
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.shrink_to(limit);
+    }
+
+    /// The total size of the cached objects
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Looks up an object, marking it as recently used
+    pub fn get(&mut self, location: PackLocation) -> Option<(ObjectType, Rc<[u8]>)> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&location)?;
+        self.usage.remove(&entry.last_used);
+        self.usage.insert(self.clock, location);
+        entry.last_used = self.clock;
+        Some((entry.object_type, entry.contents.clone()))
+    }
+
+    /// Adds an object, dropping the least recently used ones to make room.
+    /// Objects bigger than the whole cache are not kept.
+    pub fn insert(&mut self, location: PackLocation, object_type: ObjectType, contents: Rc<[u8]>) {
+        if contents.len() > self.limit {
+            return;
+        }
+        self.remove(location);
+        self.shrink_to(self.limit - contents.len());
+
+        self.clock += 1;
+        self.used += contents.len();
+        self.usage.insert(self.clock, location);
+        self.entries.insert(
+            location,
+            CacheEntry {
+                object_type,
+                contents,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    fn remove(&mut self, location: PackLocation) {
+        if let Some(entry) = self.entries.remove(&location) {
+            self.usage.remove(&entry.last_used);
+            self.used -= entry.contents.len();
+        }
+    }
+
+    // Drops the least recently used entries until at most `size` bytes are used
+    fn shrink_to(&mut self, size: usize) {
+        while self.used > size {
+            let Some((_, location)) = self.usage.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&location) {
+                self.used -= entry.contents.len();
+            }
+        }
+    }
+}
+
+impl Default for DeltaBaseCache {
+    fn default() -> DeltaBaseCache {
+        DeltaBaseCache::new(DEFAULT_DELTA_BASE_CACHE_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::test_repo::TestRepo;
+
+    fn location(offset: u64) -> PackLocation {
+        (ObjectFormat::Sha1.digest(b"pack"), offset)
+    }
+
+    fn contents(size: usize) -> Rc<[u8]> {
+        vec![0; size].into()
+    }
+
+    fn cached(cache: &mut DeltaBaseCache, offset: u64) -> bool {
+        cache.get(location(offset)).is_some()
+    }
+
+    #[test]
+    fn least_recently_used_entries_go_first() {
+        let mut cache = DeltaBaseCache::new(10);
+        cache.insert(location(1), ObjectType::Blob, contents(4));
+        cache.insert(location(2), ObjectType::Tree, contents(4));
+        // Using the first entry makes the second the oldest
+        assert_eq!(
+            cache.get(location(1)),
+            Some((ObjectType::Blob, contents(4)))
+        );
+        cache.insert(location(3), ObjectType::Blob, contents(4));
+        assert_eq!(cache.used(), 8);
+        assert!(!cached(&mut cache, 2));
+        assert!(cached(&mut cache, 1));
+        assert!(cached(&mut cache, 3));
+
+        // Now the first is the oldest again
+        cache.insert(location(4), ObjectType::Blob, contents(4));
+        assert!(!cached(&mut cache, 1));
+        assert!(cached(&mut cache, 3));
+        assert!(cached(&mut cache, 4));
+    }
+
+    #[test]
+    fn entries_stay_within_the_limit() {
+        let mut cache = DeltaBaseCache::new(10);
+        for offset in 0..5 {
+            cache.insert(location(offset), ObjectType::Blob, contents(3));
+            assert!(cache.used() <= 10);
+        }
+        assert_eq!(cache.used(), 9);
+        assert!(cached(&mut cache, 2));
+        assert!(!cached(&mut cache, 1));
+
+        // Adding an entry again replaces it rather than counting it twice
+        cache.insert(location(4), ObjectType::Blob, contents(1));
+        assert_eq!(cache.used(), 7);
+
+        // Lowering the limit drops the oldest entries. Looking up
+        // the entry at offset 2 above made the one at 3 the oldest.
+        cache.set_limit(5);
+        assert_eq!(cache.limit(), 5);
+        assert_eq!(cache.used(), 4);
+        assert!(!cached(&mut cache, 3));
+        assert!(cached(&mut cache, 2));
+        assert!(cached(&mut cache, 4));
+    }
+
+    #[test]
+    fn entries_bigger_than_the_limit_are_not_kept() {
+        let mut cache = DeltaBaseCache::new(10);
+        cache.insert(location(1), ObjectType::Blob, contents(6));
+        cache.insert(location(2), ObjectType::Blob, contents(11));
+        assert!(!cached(&mut cache, 2));
+        // and don't push anything else out
+        assert!(cached(&mut cache, 1));
+        assert_eq!(cache.used(), 6);
+
+        cache.insert(location(3), ObjectType::Blob, contents(10));
+        assert!(cached(&mut cache, 3));
+        assert!(!cached(&mut cache, 1));
+    }
+
+    #[test]
+    fn limit_comes_from_config() {
+        let test_repo = TestRepo::new();
+        let limit = |test_repo: &TestRepo| test_repo.open().delta_base_cache.borrow().limit();
+        assert_eq!(limit(&test_repo), DEFAULT_DELTA_BASE_CACHE_LIMIT);
+        for (value, expected) in [
+            ("12345", 12345),
+            ("10k", 10 << 10),
+            ("3M", 3 << 20),
+            ("1g", 1 << 30),
+            // Unreadable sizes leave the default
+            ("lots", DEFAULT_DELTA_BASE_CACHE_LIMIT),
+            ("k", DEFAULT_DELTA_BASE_CACHE_LIMIT),
+        ] {
+            test_repo.write(
+                "config",
+                format!("[core]\n\tdeltaBaseCacheLimit = {}\n", value),
+            );
+            assert_eq!(limit(&test_repo), expected, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::error;
+use std::fmt::{self, Display, Formatter};
+
+use super::MAX_RESERVED_ENTRY_BYTES;
+
+// Delta instructions with this bit set copy a range of the base object.
+// Otherwise, the low 7 bits give the number of new bytes to insert.
+const COPY_INSTRUCTION_FLAG: u8 = 1 << 7;
+// A copy with a size of 0 actually copies this many bytes
+const DEFAULT_COPY_SIZE: usize = 0x10000;
+
+/// The most bytes `delta_sizes()` reads: two sizes of up to 64 bits,
+/// at 7 bits per byte
+pub const MAX_DELTA_SIZES_BYTES: usize = 20;
+
+/// Why a delta couldn't be read or applied.
+/// The delta doesn't know where it is stored, so callers add the pack and offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaError(pub &'static str);
+
+impl Display for DeltaError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "corrupt delta: {}", self.0)
+    }
+}
+
+impl error::Error for DeltaError {}
+
+fn corrupt_delta(reason: &'static str) -> DeltaError {
+    DeltaError(reason)
+}
+
+// Takes the next byte from the delta data
+fn next_byte(delta: &mut &[u8]) -> Result<u8, DeltaError> {
+    let (&byte, delta_rest) = delta
+        .split_first()
+        .ok_or_else(|| corrupt_delta("unexpected end of data"))?;
+    *delta = delta_rest;
+    Ok(byte)
+}
+
+// Reads a size at the start of a delta.
+// Sizes are little-endian, 7 bits per byte, with the top bit
+// set on every byte except the last.
+fn read_delta_size(delta: &mut &[u8]) -> Result<usize, DeltaError> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = next_byte(delta)?;
+        let bits = ((byte & 0x7f) as usize)
+            .checked_shl(shift)
+            .filter(|bits| bits >> shift == (byte & 0x7f) as usize)
+            .ok_or_else(|| corrupt_delta("size overflows"))?;
+        size |= bits;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+        shift += 7;
+    }
+}
+
+/// Reads the sizes of the base object and the resulting object
+/// from the start of a delta
+pub fn delta_sizes(mut delta: &[u8]) -> Result<(usize, usize), DeltaError> {
+    let base_size = read_delta_size(&mut delta)?;
+    let result_size = read_delta_size(&mut delta)?;
+    Ok((base_size, result_size))
+}
+
+// Adds bytes to a delta's result, which must not grow past the size the delta gave
+fn extend_result(result: &mut Vec<u8>, bytes: &[u8], result_size: usize) -> Result<(), DeltaError> {
+    if bytes.len() > result_size - result.len() {
+        return Err(corrupt_delta("result has the wrong size"));
+    }
+    result.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// Rebuilds an object from its base object and a delta.
+/// The delta is a list of instructions, each either copying
+/// a range of the base object or inserting new bytes.
+pub fn apply_delta(base: &[u8], mut delta: &[u8]) -> Result<Vec<u8>, DeltaError> {
+    let base_size = read_delta_size(&mut delta)?;
+    if base_size != base.len() {
+        return Err(corrupt_delta("base object has the wrong size"));
+    }
+    let result_size = read_delta_size(&mut delta)?;
+    // Each byte of instructions makes at most a whole copy of the base,
+    // or one inserted byte, so a bigger size can't be right
+    if result_size > delta.len().saturating_mul(base.len().max(1)) {
+        return Err(corrupt_delta(
+            "result size is bigger than the delta can make",
+        ));
+    }
+
+    // A size under that limit can still be far more than the delta makes,
+    // so only reserve a bounded amount up front
+    let mut result = Vec::with_capacity(result_size.min(MAX_RESERVED_ENTRY_BYTES as usize));
+    while !delta.is_empty() {
+        let instruction = next_byte(&mut delta)?;
+        if instruction & COPY_INSTRUCTION_FLAG != 0 {
+            // Bits 0 to 3 say which bytes of the offset are present,
+            // and bits 4 to 6 which bytes of the size are present.
+            // Missing bytes are 0.
+            let mut offset = 0usize;
+            for byte_index in 0..4 {
+                if instruction & (1 << byte_index) != 0 {
+                    offset |= (next_byte(&mut delta)? as usize) << (8 * byte_index);
+                }
+            }
+            let mut size = 0usize;
+            for byte_index in 0..3 {
+                if instruction & (1 << (4 + byte_index)) != 0 {
+                    size |= (next_byte(&mut delta)? as usize) << (8 * byte_index);
+                }
+            }
+            if size == 0 {
+                size = DEFAULT_COPY_SIZE;
+            }
+
+            let copied = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| corrupt_delta("copy is outside the base object"))?;
+            extend_result(&mut result, copied, result_size)?;
+        } else if instruction != 0 {
+            let size = instruction as usize;
+            let inserted = delta
+                .get(..size)
+                .ok_or_else(|| corrupt_delta("insert runs past the end of the data"))?;
+            extend_result(&mut result, inserted, result_size)?;
+            delta = &delta[size..];
+        } else {
+            // Instruction 0 is reserved
+            return Err(corrupt_delta("unknown instruction 0"));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(corrupt_delta("result has the wrong size"));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A delta turning "hello world" into "hello there, world":
+    // copy 6 bytes from offset 0, insert "there, ", copy 5 bytes from offset 6
+    const BASE: &[u8] = b"hello world";
+    const DELTA: &[u8] = b"\x0b\x12\x90\x06\x07there, \x91\x06\x05";
+
+    #[test]
+    fn applies_copies_and_inserts() {
+        assert_eq!(delta_sizes(DELTA), Ok((11, 18)));
+        assert_eq!(apply_delta(BASE, DELTA).unwrap(), b"hello there, world");
+    }
+
+    #[test]
+    fn rejects_wrong_base_size() {
+        assert_eq!(
+            apply_delta(b"hello", DELTA),
+            Err(DeltaError("base object has the wrong size"))
+        );
+    }
+
+    #[test]
+    fn rejects_huge_result_size_without_allocating() {
+        // A result size of 2^63 - 1, followed by one copy instruction
+        let mut delta = b"\x0b".to_vec();
+        delta.extend_from_slice(&[0xff; 8]);
+        delta.extend_from_slice(b"\x7f\x90\x06");
+        assert_eq!(
+            apply_delta(BASE, &delta),
+            Err(DeltaError("result size is bigger than the delta can make"))
+        );
+    }
+
+    #[test]
+    fn rejects_result_shorter_than_its_size() {
+        // A 1 MiB base lets a 1 KiB delta claim a 1 GiB result,
+        // but its inserts only make 1000 bytes
+        let base = vec![0; 1 << 20];
+        let mut delta = b"\x80\x80\x40\x80\x80\x80\x04".to_vec();
+        for _ in 0..1000 {
+            delta.extend_from_slice(b"\x01!");
+        }
+        assert_eq!(
+            apply_delta(&base, &delta),
+            Err(DeltaError("result has the wrong size"))
+        );
+    }
+
+    #[test]
+    fn rejects_result_longer_than_its_size() {
+        // Says the result is 4 bytes, but copies 6
+        assert_eq!(
+            apply_delta(BASE, b"\x0b\x04\x90\x06\x01!"),
+            Err(DeltaError("result has the wrong size"))
+        );
+    }
+
+    #[test]
+    fn rejects_copy_outside_base() {
+        assert_eq!(
+            apply_delta(BASE, b"\x0b\x05\x91\x08\x05"),
+            Err(DeltaError("copy is outside the base object"))
+        );
+    }
+}
@@ -0,0 +1,569 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use super::verify::PackProblem;
+use super::INDEX_MAGIC;
+use crate::error::RustGitError;
+use crate::hash::{Hash, ObjectFormat};
+
+/// The number of entries in a fan-out table, one per possible first byte of a hash
+pub const FANOUT_ENTRIES: usize = 1 << u8::BITS;
+
+// A 4-byte offset with its top bit set is instead
+// an index into the table of 8-byte offsets
+const LARGE_OFFSET_FLAG: u32 = 1 << 31;
+
+// Where the fan-out table starts in each version.
+// Version 2 puts the magic number and version in front of it.
+const V1_FANOUT_OFFSET: usize = 0;
+const V2_FANOUT_OFFSET: usize = 8;
+
+/// The contents of a version 1 or 2 `.idx` file.
+/// The index lists every object in the matching `.pack` file,
+/// sorted by hash, along with where it starts in the pack.
+#[derive(Debug)]
+pub struct PackIndex {
+    version: u32,
+    format: ObjectFormat,
+    // For each of the 256 possible first bytes `b` of a hash,
+    // the cumulative number of objects with first byte <= `b`
+    fanout: [u32; FANOUT_ENTRIES],
+    hashes: Vec<Hash>,
+    // The CRC32 of each object's compressed data in the pack.
+    // Only version 2 indexes have these.
+    crc32s: Option<Vec<u32>>,
+    offsets: Vec<u64>,
+    pack_checksum: Hash,
+    index_checksum: Hash,
+}
+
+/// The range of positions in a sorted hash table
+/// whose hashes start with the given byte, according to a fan-out table
+pub fn fanout_range(fanout: &[u32; FANOUT_ENTRIES], first_byte: u8) -> Range<usize> {
+    let first_byte = first_byte as usize;
+    let start = match first_byte {
+        0 => 0,
+        _ => fanout[first_byte - 1] as usize,
+    };
+    start..fanout[first_byte] as usize
+}
+
+/// Finds the position of a hash in a sorted hash table.
+/// The fan-out table narrows the search down to hashes with the same first byte,
+/// then a binary search finds the hash among them.
+pub fn fanout_search(
+    fanout: &[u32; FANOUT_ENTRIES],
+    hashes: &[Hash],
+    hash: &Hash,
+) -> Option<usize> {
+    let range = fanout_range(fanout, hash.first_byte());
+    let start = range.start;
+    // A corrupt fan-out table could point outside the hash table
+    let bucket = hashes.get(range)?;
+    let position = bucket.binary_search(hash).ok()?;
+    Some(start + position)
+}
+
+// The hashes, CRC32s and offsets of all the objects in an index
+type IndexTables = (Vec<Hash>, Option<Vec<u32>>, Vec<u64>);
+
+// Checks that the index is long enough to hold `length` bytes at an offset
+fn check_length(data: &[u8], offset: usize, length: usize) -> Result<(), PackProblem> {
+    if offset + length > data.len() {
+        return Err(PackProblem::IndexTruncated {
+            length: data.len() as u64,
+            expected: (offset + length) as u64,
+        });
+    }
+    Ok(())
+}
+
+// These read a value at an offset that `check_length()` has already checked
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn hash_at(data: &[u8], offset: usize, format: ObjectFormat) -> Hash {
+    Hash::new(format, &data[offset..offset + format.hash_bytes()])
+}
+
+// Each version 1 entry is a 4-byte offset followed by a hash
+fn v1_entry_bytes(format: ObjectFormat) -> usize {
+    4 + format.hash_bytes()
+}
+
+// An index ends with the pack's checksum and its own
+fn trailer_bytes(format: ObjectFormat) -> usize {
+    2 * format.hash_bytes()
+}
+
+// Version 1 stores a 4-byte offset and a hash for each object, side by side
+fn read_v1_tables(
+    data: &[u8],
+    total_objects: usize,
+    format: ObjectFormat,
+) -> Result<IndexTables, PackProblem> {
+    let table_offset = V1_FANOUT_OFFSET + FANOUT_ENTRIES * 4;
+    let entry_bytes = v1_entry_bytes(format);
+    check_length(
+        data,
+        table_offset,
+        total_objects * entry_bytes + trailer_bytes(format),
+    )?;
+    let entry_offsets = (0..total_objects).map(|position| table_offset + position * entry_bytes);
+    let offsets = entry_offsets
+        .clone()
+        .map(|entry| u32_at(data, entry) as u64)
+        .collect();
+    let hashes = entry_offsets
+        .map(|entry| hash_at(data, entry + 4, format))
+        .collect();
+    Ok((hashes, None, offsets))
+}
+
+// Version 2 stores a series of tables,
+// each with one entry per object in hash order
+fn read_v2_tables(
+    data: &[u8],
+    total_objects: usize,
+    format: ObjectFormat,
+) -> Result<IndexTables, PackProblem> {
+    let hash_bytes = format.hash_bytes();
+    let hashes_offset = V2_FANOUT_OFFSET + FANOUT_ENTRIES * 4;
+    let crc32s_offset = hashes_offset + total_objects * hash_bytes;
+    let offsets_offset = crc32s_offset + total_objects * 4;
+    let large_offsets_offset = offsets_offset + total_objects * 4;
+    check_length(data, 0, large_offsets_offset + trailer_bytes(format))?;
+
+    let hashes = (0..total_objects)
+        .map(|position| hash_at(data, hashes_offset + position * hash_bytes, format))
+        .collect();
+    let crc32s = (0..total_objects)
+        .map(|position| u32_at(data, crc32s_offset + position * 4))
+        .collect();
+
+    // Only packs over 2 GiB need 8-byte offsets.
+    // The table has no length of its own; it runs up to the checksums.
+    let large_offsets_end = data.len() - trailer_bytes(format);
+    let offsets = (0..total_objects)
+        .map(|position| {
+            let offset_offset = offsets_offset + position * 4;
+            let offset = u32_at(data, offset_offset);
+            if offset & LARGE_OFFSET_FLAG == 0 {
+                return Ok(offset as u64);
+            }
+            let large_offset = large_offsets_offset + (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+            if large_offset + 8 > large_offsets_end {
+                return Err(PackProblem::LargeOffsetOutOfRange {
+                    hash: hash_at(data, hashes_offset + position * hash_bytes, format),
+                    offset: offset_offset as u64,
+                });
+            }
+            Ok(u64_at(data, large_offset))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((hashes, Some(crc32s), offsets))
+}
+
+impl PackIndex {
+    /// Reads an `.idx` file from a repository using `format` for its hashes
+    pub fn open(path: &Path, format: ObjectFormat) -> io::Result<PackIndex> {
+        let data = fs::read(path)?;
+        PackIndex::parse(&data, format).map_err(|problem| {
+            RustGitError::PackError {
+                path: path.to_path_buf(),
+                problem,
+            }
+            .into()
+        })
+    }
+
+    /// Parses the contents of an `.idx` file, checking that the fan-out table
+    /// never decreases and that the file is as long as the table says.
+    /// The hashes aren't checked against their buckets here; see `verify::check_fanout()`.
+    pub fn parse(data: &[u8], format: ObjectFormat) -> Result<PackIndex, PackProblem> {
+        // Version 1 indexes have no header, so they start straight away
+        // with the fan-out table. The magic number can't be mistaken for
+        // the start of a fan-out table, since that would mean billions of objects.
+        check_length(data, 0, V2_FANOUT_OFFSET)?;
+        let version = if data[..4] == INDEX_MAGIC {
+            let version = u32_at(data, 4);
+            if version != 2 {
+                return Err(PackProblem::UnsupportedIndexVersion { version });
+            }
+            version
+        } else {
+            1
+        };
+        let fanout_offset = match version {
+            1 => V1_FANOUT_OFFSET,
+            _ => V2_FANOUT_OFFSET,
+        };
+
+        check_length(data, fanout_offset, FANOUT_ENTRIES * 4)?;
+        let mut fanout = [0; FANOUT_ENTRIES];
+        for bucket in 0..FANOUT_ENTRIES {
+            let offset = fanout_offset + bucket * 4;
+            fanout[bucket] = u32_at(data, offset);
+            // Each entry counts the objects in its bucket and all the ones before,
+            // so it can never be less than the entry before it
+            if bucket > 0 && fanout[bucket] < fanout[bucket - 1] {
+                return Err(PackProblem::FanoutNotMonotonic {
+                    bucket: bucket as u8,
+                    offset: offset as u64,
+                    objects: fanout[bucket],
+                    previous: fanout[bucket - 1],
+                });
+            }
+        }
+        // `fanout[255]` is the total number of objects
+        let total_objects = fanout[FANOUT_ENTRIES - 1] as usize;
+
+        let (hashes, crc32s, offsets) = match version {
+            1 => read_v1_tables(data, total_objects, format)?,
+            _ => read_v2_tables(data, total_objects, format)?,
+        };
+
+        let trailer_offset = data.len() - trailer_bytes(format);
+        Ok(PackIndex {
+            version,
+            format,
+            fanout,
+            hashes,
+            crc32s,
+            offsets,
+            pack_checksum: hash_at(data, trailer_offset, format),
+            index_checksum: hash_at(data, trailer_offset + format.hash_bytes(), format),
+        })
+    }
+
+    /// Where the hash at a position in `hashes()` is stored in the index file
+    pub fn hash_offset(&self, position: usize) -> u64 {
+        let offset = match self.version {
+            1 => V1_FANOUT_OFFSET + FANOUT_ENTRIES * 4 + position * v1_entry_bytes(self.format) + 4,
+            _ => V2_FANOUT_OFFSET + FANOUT_ENTRIES * 4 + position * self.format.hash_bytes(),
+        };
+        offset as u64
+    }
+
+    /// The index format version, either 1 or 2
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The hash function the index's hashes and checksums use
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    /// The number of objects in the pack
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn fanout(&self) -> &[u32; FANOUT_ENTRIES] {
+        &self.fanout
+    }
+
+    /// The hashes of all the objects in the pack, in sorted order
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    /// The offset in the pack of the object at a position in `hashes()`
+    pub fn offset(&self, position: usize) -> u64 {
+        self.offsets[position]
+    }
+
+    /// The CRC32 of the object at a position in `hashes()`.
+    /// Version 1 indexes don't record CRC32s.
+    pub fn crc32(&self, position: usize) -> Option<u32> {
+        self.crc32s.as_ref().map(|crc32s| crc32s[position])
+    }
+
+    /// The checksum of the `.pack` file this index describes
+    pub fn pack_checksum(&self) -> Hash {
+        self.pack_checksum
+    }
+
+    /// The checksum of the index file itself, excluding this checksum
+    pub fn index_checksum(&self) -> Hash {
+        self.index_checksum
+    }
+
+    /// Finds the position of an object in `hashes()`
+    pub fn position(&self, hash: &Hash) -> Option<usize> {
+        fanout_search(&self.fanout, &self.hashes, hash)
+    }
+
+    /// Finds an object's offset in the pack and the CRC32 of its packed data,
+    /// if the index records one
+    pub fn lookup(&self, hash: &Hash) -> Option<(u64, Option<u32>)> {
+        let position = self.position(hash)?;
+        Some((self.offsets[position], self.crc32(position)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::verify::check_fanout;
+    use crate::test_repo::fanout_bytes;
+
+    const FORMAT: ObjectFormat = ObjectFormat::Sha1;
+    // Where the hash table starts in a version 2 index
+    const V2_HASHES_OFFSET: usize = V2_FANOUT_OFFSET + FANOUT_ENTRIES * 4;
+
+    // A hash starting with one byte and ending with another
+    fn hash(first_byte: u8, last_byte: u8) -> Hash {
+        let mut bytes = [0; 20];
+        bytes[0] = first_byte;
+        bytes[19] = last_byte;
+        Hash::new(FORMAT, &bytes)
+    }
+
+    fn trailer() -> Vec<u8> {
+        [hash(0xaa, 0).as_bytes(), hash(0xbb, 0).as_bytes()].concat()
+    }
+
+    // A version 1 index of objects sorted by hash, each with its offset
+    fn v1_index(objects: &[(Hash, u64)]) -> Vec<u8> {
+        let hashes: Vec<Hash> = objects.iter().map(|&(hash, _)| hash).collect();
+        let mut index = fanout_bytes(&hashes);
+        for (hash, offset) in objects {
+            index.extend_from_slice(&(*offset as u32).to_be_bytes());
+            index.extend_from_slice(hash.as_bytes());
+        }
+        index.extend_from_slice(&trailer());
+        index
+    }
+
+    // A version 2 index of objects sorted by hash, each with its CRC32 and offset.
+    // Offsets that don't fit in 31 bits go in the table of 8-byte offsets.
+    fn v2_index(objects: &[(Hash, u32, u64)]) -> Vec<u8> {
+        let hashes: Vec<Hash> = objects.iter().map(|&(hash, _, _)| hash).collect();
+        let mut index = INDEX_MAGIC.to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&fanout_bytes(&hashes));
+        for hash in &hashes {
+            index.extend_from_slice(hash.as_bytes());
+        }
+        for (_, crc32, _) in objects {
+            index.extend_from_slice(&crc32.to_be_bytes());
+        }
+        let mut large_offsets = vec![];
+        for &(_, _, offset) in objects {
+            let offset = match u32::try_from(offset) {
+                Ok(offset) if offset & LARGE_OFFSET_FLAG == 0 => offset,
+                _ => {
+                    large_offsets.push(offset);
+                    LARGE_OFFSET_FLAG | (large_offsets.len() - 1) as u32
+                }
+            };
+            index.extend_from_slice(&offset.to_be_bytes());
+        }
+        for offset in large_offsets {
+            index.extend_from_slice(&offset.to_be_bytes());
+        }
+        index.extend_from_slice(&trailer());
+        index
+    }
+
+    fn objects() -> Vec<(Hash, u32, u64)> {
+        vec![
+            (hash(0x10, 1), 0x1111, 12),
+            (hash(0x20, 1), 0x2222, 100),
+            (hash(0x20, 2), 0x3333, 200),
+            (hash(0x30, 1), 0x4444, 300),
+        ]
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let objects: Vec<_> = objects()
+            .into_iter()
+            .map(|(hash, _, offset)| (hash, offset))
+            .collect();
+        let index = PackIndex::parse(&v1_index(&objects), FORMAT).unwrap();
+        assert_eq!(index.version(), 1);
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.fanout()[0x1f], 1);
+        assert_eq!(index.fanout()[0x20], 3);
+        for (position, &(hash, offset)) in objects.iter().enumerate() {
+            assert_eq!(index.hashes()[position], hash);
+            assert_eq!(index.offset(position), offset);
+            assert_eq!(index.crc32(position), None);
+            assert_eq!(index.lookup(&hash), Some((offset, None)));
+        }
+        assert_eq!(index.lookup(&hash(0x20, 3)), None);
+        assert_eq!(index.pack_checksum(), hash(0xaa, 0));
+        assert_eq!(index.index_checksum(), hash(0xbb, 0));
+        // Each entry is a 4-byte offset, then the hash
+        assert_eq!(index.hash_offset(2), (1024 + 2 * 24 + 4) as u64);
+    }
+
+    #[test]
+    fn reads_version_2() {
+        let objects = objects();
+        let index = PackIndex::parse(&v2_index(&objects), FORMAT).unwrap();
+        assert_eq!(index.version(), 2);
+        assert_eq!(index.len(), 4);
+        for (position, &(hash, crc32, offset)) in objects.iter().enumerate() {
+            assert_eq!(index.position(&hash), Some(position));
+            assert_eq!(index.crc32(position), Some(crc32));
+            assert_eq!(index.lookup(&hash), Some((offset, Some(crc32))));
+        }
+        assert_eq!(index.lookup(&hash(0x40, 0)), None);
+        assert_eq!(index.pack_checksum(), hash(0xaa, 0));
+        assert_eq!(index.hash_offset(2), (V2_HASHES_OFFSET + 2 * 20) as u64);
+    }
+
+    #[test]
+    fn reads_large_offsets() {
+        let mut objects = objects();
+        objects[1].2 = 1 << 31;
+        objects[3].2 = 5 << 32;
+        let index = PackIndex::parse(&v2_index(&objects), FORMAT).unwrap();
+        for (position, &(_, _, offset)) in objects.iter().enumerate() {
+            assert_eq!(index.offset(position), offset);
+        }
+    }
+
+    #[test]
+    fn large_offset_past_the_table() {
+        let mut objects = objects();
+        objects[3].2 = 5 << 32;
+        let mut data = v2_index(&objects);
+        // Point the last object at the second entry of a 1-entry table
+        let offset_offset = V2_HASHES_OFFSET + 4 * 20 + 4 * 4 + 3 * 4;
+        data[offset_offset + 3] = 1;
+        assert_eq!(
+            PackIndex::parse(&data, FORMAT).unwrap_err(),
+            PackProblem::LargeOffsetOutOfRange {
+                hash: objects[3].0,
+                offset: offset_offset as u64,
+            }
+        );
+    }
+
+    #[test]
+    fn fanout_must_not_decrease() {
+        // Lower the count for bucket 0x25 from 3 to 0
+        let mut data = v2_index(&objects());
+        let offset = V2_FANOUT_OFFSET + 0x25 * 4;
+        data[offset + 3] = 0;
+        assert_eq!(
+            PackIndex::parse(&data, FORMAT).unwrap_err(),
+            PackProblem::FanoutNotMonotonic {
+                bucket: 0x25,
+                offset: offset as u64,
+                objects: 0,
+                previous: 3,
+            }
+        );
+
+        let objects: Vec<_> = objects()
+            .into_iter()
+            .map(|(hash, _, offset)| (hash, offset))
+            .collect();
+        let mut data = v1_index(&objects);
+        data[0x25 * 4 + 3] = 0;
+        assert_eq!(
+            PackIndex::parse(&data, FORMAT).unwrap_err(),
+            PackProblem::FanoutNotMonotonic {
+                bucket: 0x25,
+                offset: 0x25 * 4,
+                objects: 0,
+                previous: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn index_must_hold_every_table() {
+        let data = v2_index(&objects());
+        let length = data.len() as u64;
+        assert_eq!(
+            PackIndex::parse(&data[..data.len() - 1], FORMAT).unwrap_err(),
+            PackProblem::IndexTruncated {
+                length: length - 1,
+                expected: length,
+            }
+        );
+        assert_eq!(
+            PackIndex::parse(&data[..4], FORMAT).unwrap_err(),
+            PackProblem::IndexTruncated {
+                length: 4,
+                expected: V2_FANOUT_OFFSET as u64,
+            }
+        );
+        assert_eq!(
+            PackIndex::parse(&data[..100], FORMAT).unwrap_err(),
+            PackProblem::IndexTruncated {
+                length: 100,
+                expected: V2_HASHES_OFFSET as u64,
+            }
+        );
+
+        let mut data = data;
+        data[7] = 3;
+        assert_eq!(
+            PackIndex::parse(&data, FORMAT).unwrap_err(),
+            PackProblem::UnsupportedIndexVersion { version: 3 }
+        );
+    }
+
+    #[test]
+    fn hashes_must_be_in_their_bucket() {
+        // Change the first byte of the last hash in bucket 0x20 to 0x21
+        let objects = objects();
+        let mut data = v2_index(&objects);
+        let offset = V2_HASHES_OFFSET + 2 * 20;
+        data[offset] = 0x21;
+        let index = PackIndex::parse(&data, FORMAT).unwrap();
+        let mut moved = [0; 20];
+        moved[0] = 0x21;
+        moved[19] = 2;
+        assert_eq!(
+            check_fanout(&index),
+            vec![PackProblem::WrongBucket {
+                hash: Hash::new(FORMAT, &moved),
+                bucket: 0x20,
+                offset: offset as u64,
+            }]
+        );
+    }
+
+    #[test]
+    fn hashes_must_be_sorted() {
+        // Change the last byte of the third hash so it equals the second
+        let objects = objects();
+        let mut data = v2_index(&objects);
+        let offset = V2_HASHES_OFFSET + 2 * 20;
+        data[offset + 19] = 1;
+        let index = PackIndex::parse(&data, FORMAT).unwrap();
+        assert_eq!(
+            check_fanout(&index),
+            vec![PackProblem::Unsorted {
+                hash: objects[1].0,
+                previous: objects[1].0,
+                bucket: 0x20,
+                offset: offset as u64,
+            }]
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use super::index::{fanout_search, FANOUT_ENTRIES};
+use super::{read_bytes, read_hash, read_u32, read_u64};
+use crate::chunk::{find_chunk, read_chunks};
+use crate::error::RustGitError;
+use crate::hash::{Hash, ObjectFormat};
+use crate::repository::Repository;
+
+const MIDX_MAGIC: [u8; 4] = *b"MIDX";
+// The magic number, 4 bytes of versions and counts, and the number of packs
+const HEADER_BYTES: usize = 12;
+
+const PACK_NAMES_CHUNK: [u8; 4] = *b"PNAM";
+const FANOUT_CHUNK: [u8; 4] = *b"OIDF";
+const HASHES_CHUNK: [u8; 4] = *b"OIDL";
+const OFFSETS_CHUNK: [u8; 4] = *b"OOFF";
+const LARGE_OFFSETS_CHUNK: [u8; 4] = *b"LOFF";
+
+// An offset with its top bit set is instead
+// an index into the table of 8-byte offsets
+const LARGE_OFFSET_FLAG: u32 = 1 << 31;
+
+// Whether git should use the multi-pack-index, on by default
+const MULTI_PACK_INDEX_CONFIG: &str = "core.multiPackIndex";
+
+/// The contents of an `objects/pack/multi-pack-index` file.
+/// This is a single index covering the objects in many packs,
+/// so an object can be found without searching each pack's index in turn.
+#[derive(Debug)]
+pub struct MultiPackIndex {
+    // The `.idx` file names of the packs covered, in sorted order
+    pack_names: Vec<String>,
+    // For each of the 256 possible first bytes `b` of a hash,
+    // the cumulative number of objects with first byte <= `b`
+    fanout: [u32; FANOUT_ENTRIES],
+    hashes: Vec<Hash>,
+    // For each object, the position of its pack in `pack_names`
+    // and its offset in that pack
+    locations: Vec<(u32, u64)>,
+}
+
+fn corrupt_midx(reason: &str) -> Error {
+    RustGitError::CorruptMultiPackIndex(reason.to_string()).into()
+}
+
+impl MultiPackIndex {
+    pub fn open(path: &Path, format: ObjectFormat) -> io::Result<MultiPackIndex> {
+        MultiPackIndex::parse(&fs::read(path)?, format)
+    }
+
+    pub fn parse(data: &[u8], format: ObjectFormat) -> io::Result<MultiPackIndex> {
+        let mut header = data
+            .get(..HEADER_BYTES)
+            .ok_or_else(|| corrupt_midx("file is too short"))?;
+        let magic = read_bytes(&mut header)?;
+        if magic != MIDX_MAGIC {
+            return Err(corrupt_midx("wrong magic number"));
+        }
+        let [version, hash_version, chunk_count, base_files] = read_bytes(&mut header)?;
+        if version != 1 {
+            return Err(corrupt_midx(&format!("unsupported version {}", version)));
+        }
+        if hash_version != format.hash_version() {
+            return Err(corrupt_midx(&format!(
+                "unsupported hash version {}",
+                hash_version
+            )));
+        }
+        if base_files != 0 {
+            return Err(corrupt_midx(
+                "base multi-pack-index files are not supported",
+            ));
+        }
+        let pack_count = read_u32(&mut header)? as usize;
+
+        let chunks = read_chunks(data, HEADER_BYTES, chunk_count as usize)
+            .ok_or_else(|| corrupt_midx("chunk table is invalid"))?;
+        let required_chunk = |id| {
+            find_chunk(&chunks, id).ok_or_else(|| {
+                corrupt_midx(&format!("missing {} chunk", String::from_utf8_lossy(&id)))
+            })
+        };
+
+        // The pack names are each terminated by a NUL byte,
+        // with more NUL bytes padding the end of the chunk
+        let pack_names = required_chunk(PACK_NAMES_CHUNK)?
+            .split(|&byte| byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect::<Vec<_>>();
+        if pack_names.len() != pack_count {
+            return Err(corrupt_midx("pack count differs from pack names"));
+        }
+
+        let mut fanout_chunk = required_chunk(FANOUT_CHUNK)?;
+        let mut fanout = [0; FANOUT_ENTRIES];
+        for objects in &mut fanout {
+            *objects = read_u32(&mut fanout_chunk)?;
+        }
+        // `fanout[255]` is the total number of objects
+        let total_objects = fanout[FANOUT_ENTRIES - 1] as usize;
+
+        let mut hashes_chunk = required_chunk(HASHES_CHUNK)?;
+        if hashes_chunk.len() != total_objects * format.hash_bytes() {
+            return Err(corrupt_midx("hash table has the wrong size"));
+        }
+        let hashes = (0..total_objects)
+            .map(|_| read_hash(&mut hashes_chunk, format))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // Only packs over 2 GiB need 8-byte offsets, so this chunk is optional
+        let large_offsets = find_chunk(&chunks, LARGE_OFFSETS_CHUNK).unwrap_or_default();
+        let mut offsets_chunk = required_chunk(OFFSETS_CHUNK)?;
+        let locations = (0..total_objects)
+            .map(|_| {
+                let pack = read_u32(&mut offsets_chunk)?;
+                if pack as usize >= pack_count {
+                    return Err(corrupt_midx("object is in an unknown pack"));
+                }
+                let offset = read_u32(&mut offsets_chunk)?;
+                if offset & LARGE_OFFSET_FLAG == 0 {
+                    return Ok((pack, offset as u64));
+                }
+                let large_offset_start = (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+                let mut large_offset = large_offsets
+                    .get(large_offset_start..large_offset_start + 8)
+                    .ok_or_else(|| corrupt_midx("large offset is out of range"))?;
+                Ok((pack, read_u64(&mut large_offset)?))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(MultiPackIndex {
+            pack_names,
+            fanout,
+            hashes,
+            locations,
+        })
+    }
+
+    /// The `.idx` file names of the packs this index covers
+    pub fn pack_names(&self) -> &[String] {
+        &self.pack_names
+    }
+
+    /// The number of objects in all the packs
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// The hashes of all the objects, in sorted order
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    /// Finds which pack has an object, returning the pack's `.idx` file name
+    /// and the object's offset in the pack
+    pub fn lookup(&self, hash: &Hash) -> Option<(&str, u64)> {
+        let position = fanout_search(&self.fanout, &self.hashes, hash)?;
+        let (pack, offset) = self.locations[position];
+        Some((&self.pack_names[pack as usize], offset))
+    }
+}
+
+impl Repository {
+    // Reads the multi-pack-index the first time it is needed,
+    // keeping the error instead if it is corrupt
+    fn load_multi_pack_index(&self) -> &io::Result<Option<MultiPackIndex>> {
+        self.multi_pack_index.get_or_init(|| {
+            if self.config().get_bool(MULTI_PACK_INDEX_CONFIG) == Some(false) {
+                return Ok(None);
+            }
+            match MultiPackIndex::open(
+                &self.packs_dir().join("multi-pack-index"),
+                self.object_format(),
+            ) {
+                Ok(multi_pack_index) => Ok(Some(multi_pack_index)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    /// The multi-pack-index in `objects/pack`, loaded the first time it is needed.
+    /// Returns None if there isn't one or `core.multiPackIndex` is off.
+    /// Like git, a corrupt one is ignored, so objects are still found by
+    /// searching each pack's own index; `multi_pack_index_error()` says what was wrong.
+    pub fn multi_pack_index(&self) -> Option<&MultiPackIndex> {
+        self.load_multi_pack_index().as_ref().ok()?.as_ref()
+    }
+
+    /// Why the multi-pack-index was ignored, if it is corrupt
+    pub fn multi_pack_index_error(&self) -> Option<&Error> {
+        self.load_multi_pack_index().as_ref().err()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectType;
+    use crate::test_repo::{chunk_file, fanout_bytes, TestPack, TestRepo};
+
+    // Builds a multi-pack-index from each object's hash, pack position and 4-byte offset,
+    // which must be sorted by hash, and the table of 8-byte offsets
+    fn midx_bytes(
+        format: ObjectFormat,
+        pack_names: &[&str],
+        objects: &[(Hash, u32, u32)],
+        large_offsets: &[u64],
+    ) -> Vec<u8> {
+        let mut names = vec![];
+        for name in pack_names {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        names.resize(names.len().next_multiple_of(4), 0);
+        let hashes: Vec<Hash> = objects.iter().map(|&(hash, _, _)| hash).collect();
+        let mut chunks = vec![
+            (PACK_NAMES_CHUNK, names),
+            (FANOUT_CHUNK, fanout_bytes(&hashes)),
+            (
+                HASHES_CHUNK,
+                hashes
+                    .iter()
+                    .flat_map(|hash| hash.as_bytes().to_vec())
+                    .collect(),
+            ),
+            (
+                OFFSETS_CHUNK,
+                objects
+                    .iter()
+                    .flat_map(|(_, pack, offset)| {
+                        [pack.to_be_bytes(), offset.to_be_bytes()].concat()
+                    })
+                    .collect(),
+            ),
+        ];
+        if !large_offsets.is_empty() {
+            chunks.push((
+                LARGE_OFFSETS_CHUNK,
+                large_offsets
+                    .iter()
+                    .flat_map(|offset| offset.to_be_bytes())
+                    .collect(),
+            ));
+        }
+
+        let mut header = MIDX_MAGIC.to_vec();
+        header.extend_from_slice(&[1, format.hash_version(), chunks.len() as u8, 0]);
+        header.extend_from_slice(&(pack_names.len() as u32).to_be_bytes());
+        chunk_file(&header, &chunks)
+    }
+
+    fn index_name(pack: &TestPack) -> String {
+        let file_name = pack.index_path.file_name().unwrap();
+        file_name.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn lookup_opens_only_the_named_pack() {
+        let test_repo = TestRepo::new();
+        let pack1 = test_repo.write_pack(&[(ObjectType::Blob, b"in pack 1\n")]);
+        let pack2 = test_repo.write_pack(&[(ObjectType::Blob, b"in pack 2\n")]);
+        let mut names = [index_name(&pack1), index_name(&pack2)];
+        names.sort();
+        let position = |pack: &TestPack| names.iter().position(|name| *name == index_name(pack));
+        let (hash1, offset1) = pack1.objects[0];
+        let (hash2, offset2) = pack2.objects[0];
+        let mut objects = [
+            (hash1, position(&pack1).unwrap() as u32, offset1 as u32),
+            (hash2, position(&pack2).unwrap() as u32, offset2 as u32),
+        ];
+        objects.sort();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        test_repo.write(
+            "objects/pack/multi-pack-index",
+            midx_bytes(ObjectFormat::Sha1, &names, &objects, &[]),
+        );
+
+        let repository = test_repo.open();
+        let (pack, offset) = repository.find_packed_object(hash1).unwrap().unwrap();
+        assert_eq!(pack.path(), pack1.pack_path);
+        assert_eq!(offset, offset1);
+        let opened = repository.packs.get().unwrap();
+        let opened: Vec<_> = opened.iter().filter(|slot| slot.is_open()).collect();
+        assert_eq!(opened.len(), 1);
+    }
+
+    #[test]
+    fn corrupt_midx_falls_back_to_pack_indexes() {
+        let test_repo = TestRepo::new();
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, b"packed\n")]);
+        test_repo.write("objects/pack/multi-pack-index", b"MIDX but nothing else");
+
+        let repository = test_repo.open();
+        assert!(repository.multi_pack_index().is_none());
+        assert!(repository.multi_pack_index_error().is_some());
+        let (hash, offset) = pack.objects[0];
+        let (_, found_offset) = repository.find_packed_object(hash).unwrap().unwrap();
+        assert_eq!(found_offset, offset);
+        assert_eq!(
+            repository.read_object_contents(hash).unwrap(),
+            (ObjectType::Blob, b"packed\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn large_offset_out_of_range_is_corrupt() {
+        let hash = ObjectFormat::Sha1.digest(b"any object");
+        // Points at the second entry of a table holding only one
+        let data = midx_bytes(
+            ObjectFormat::Sha1,
+            &["pack-1.idx"],
+            &[(hash, 0, LARGE_OFFSET_FLAG | 1)],
+            &[1 << 32],
+        );
+        let error = MultiPackIndex::parse(&data, ObjectFormat::Sha1).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::CorruptMultiPackIndex(
+                "large offset is out of range".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn sha256_midx_holds_32_byte_hashes() {
+        let format = ObjectFormat::Sha256;
+        let test_repo = TestRepo::with_format(format);
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, b"sha256\n")]);
+        let (hash, offset) = pack.objects[0];
+        test_repo.write(
+            "objects/pack/multi-pack-index",
+            midx_bytes(
+                format,
+                &[&index_name(&pack)],
+                &[(hash, 0, offset as u32)],
+                &[],
+            ),
+        );
+
+        let repository = test_repo.open();
+        let midx = repository.multi_pack_index().unwrap();
+        assert_eq!(midx.hashes(), [hash]);
+        assert_eq!(
+            midx.lookup(&hash),
+            Some((index_name(&pack).as_str(), offset))
+        );
+        assert_eq!(
+            repository.read_object_contents(hash).unwrap(),
+            (ObjectType::Blob, b"sha256\n".to_vec())
+        );
+
+        // A SHA-1 multi-pack-index is no use to a SHA-256 repository
+        let data = midx_bytes(ObjectFormat::Sha1, &[&index_name(&pack)], &[], &[]);
+        assert!(MultiPackIndex::parse(&data, format).is_err());
+    }
+}
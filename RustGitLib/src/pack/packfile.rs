@@ -0,0 +1,553 @@
+use flate2::bufread::ZlibDecoder;
+use std::cell::OnceCell;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::delta::{apply_delta, delta_sizes, MAX_DELTA_SIZES_BYTES};
+use super::verify::PackProblem;
+use super::{read_bytes, read_hash, read_u32, PackIndex, MAX_RESERVED_ENTRY_BYTES};
+use crate::error::RustGitError;
+use crate::hash::{Hash, ObjectFormat};
+use crate::object::ObjectType;
+use crate::repository::Repository;
+
+const PACK_MAGIC: [u8; 4] = *b"PACK";
+
+// The top bit of each byte in a variable-length number
+// says whether more bytes follow
+const CONTINUATION_FLAG: u8 = 1 << 7;
+
+/// How an object is stored in a pack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    /// The whole object, compressed
+    Base(ObjectType),
+    /// A delta against the object at an earlier offset in the same pack
+    OffsetDelta { base_offset: u64 },
+    /// A delta against the object with the given hash
+    RefDelta { base: Hash },
+}
+
+/// The header in front of each object in a pack
+#[derive(Clone, Copy, Debug)]
+pub struct EntryHeader {
+    pub kind: EntryKind,
+    /// The size of the entry's data once inflated.
+    /// For deltas, this is the size of the delta, not of the object.
+    pub size: u64,
+    /// Where the compressed data starts in the pack
+    pub data_offset: u64,
+}
+
+/// One entry on the way from an object to the end of its chain of deltas
+#[derive(Clone, Copy, Debug)]
+pub enum ChainLink {
+    /// A delta against the next link
+    Delta { offset: u64, header: EntryHeader },
+    /// The whole object the chain ends at
+    Base {
+        offset: u64,
+        header: EntryHeader,
+        object_type: ObjectType,
+    },
+    /// The chain ends at an object outside the pack, named by a REF_DELTA
+    External(Hash),
+}
+
+/// Walks from an entry in a pack through its chain of delta bases,
+/// reading each entry's header in turn. The walk ends after the base,
+/// or after an error, such as a chain that goes round in a circle.
+pub struct DeltaChain<'a> {
+    pack: &'a Pack,
+    file: &'a mut BufReader<File>,
+    // The entry to read next, or the base outside the pack,
+    // or None once the chain has ended
+    next: Option<Result<u64, Hash>>,
+    // The number of entries read so far
+    entries: usize,
+}
+
+impl DeltaChain<'_> {
+    /// The pack file, for reading the data of the entries on the way
+    pub fn file(&mut self) -> &mut BufReader<File> {
+        self.file
+    }
+
+    /// The offset of the entry the next link will be read from,
+    /// or None if the chain has ended or leaves the pack
+    pub fn next_offset(&self) -> Option<u64> {
+        self.next?.ok()
+    }
+
+    /// Reads the next link, treating a chain that has already ended as corrupt
+    pub fn next_link(&mut self, offset: u64) -> io::Result<ChainLink> {
+        self.next().unwrap_or_else(|| {
+            Err(corrupt_pack(
+                self.pack.path(),
+                offset,
+                "delta chain has no base",
+            ))
+        })
+    }
+}
+
+impl Iterator for DeltaChain<'_> {
+    type Item = io::Result<ChainLink>;
+
+    fn next(&mut self) -> Option<io::Result<ChainLink>> {
+        let offset = match self.next.take()? {
+            Ok(offset) => offset,
+            Err(base) => return Some(Ok(ChainLink::External(base))),
+        };
+        // A chain longer than the number of objects must go round in a circle
+        if self.entries >= self.pack.index().len() {
+            return Some(Err(corrupt_pack(
+                self.pack.path(),
+                offset,
+                "delta chain has a cycle",
+            )));
+        }
+        let header = match self.pack.read_entry_header(self.file, offset) {
+            Ok(header) => header,
+            Err(e) => return Some(Err(e)),
+        };
+        self.entries += 1;
+        Some(Ok(match header.kind {
+            EntryKind::Base(object_type) => ChainLink::Base {
+                offset,
+                header,
+                object_type,
+            },
+            EntryKind::OffsetDelta { base_offset } => {
+                self.next = Some(Ok(base_offset));
+                ChainLink::Delta { offset, header }
+            }
+            EntryKind::RefDelta { base } => {
+                // The base may be stored anywhere else in the repository
+                self.next = Some(match self.pack.index().lookup(&base) {
+                    Some((base_offset, _)) => Ok(base_offset),
+                    None => Err(base),
+                });
+                ChainLink::Delta { offset, header }
+            }
+        }))
+    }
+}
+
+/// A `.pack` file and its index
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+}
+
+// A pack found in `objects/pack`. Opening it means reading its whole index,
+// so that waits until an object is looked for in it.
+#[derive(Debug)]
+pub(crate) struct PackSlot {
+    // The `.idx` file name, as the multi-pack-index names packs
+    index_name: String,
+    index_path: PathBuf,
+    pack: OnceCell<Pack>,
+}
+
+pub(crate) fn corrupt_pack(pack: &Path, offset: u64, reason: &str) -> Error {
+    RustGitError::PackError {
+        path: pack.to_path_buf(),
+        problem: PackProblem::Corrupt {
+            offset,
+            reason: reason.to_string(),
+        },
+    }
+    .into()
+}
+
+impl PackSlot {
+    #[cfg(test)]
+    pub(crate) fn is_open(&self) -> bool {
+        self.pack.get().is_some()
+    }
+}
+
+impl Pack {
+    /// Opens a pack given the path of its `.idx` file
+    /// and the hash function of its repository
+    pub fn open(index_path: &Path, format: ObjectFormat) -> io::Result<Pack> {
+        let index = PackIndex::open(index_path, format)?;
+        let path = index_path.with_extension("pack");
+
+        // Check the pack header agrees with the index
+        let mut file = BufReader::new(File::open(&path)?);
+        let magic = read_bytes(&mut file)?;
+        if magic != PACK_MAGIC {
+            return Err(corrupt_pack(&path, 0, "wrong magic number"));
+        }
+        let version = read_u32(&mut file)?;
+        if version != 2 && version != 3 {
+            return Err(corrupt_pack(&path, 4, "unsupported version"));
+        }
+        let objects = read_u32(&mut file)?;
+        if objects as usize != index.len() {
+            return Err(corrupt_pack(&path, 8, "object count differs from index"));
+        }
+        Ok(Pack { path, index })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of the pack's `.idx` file
+    pub fn index_path(&self) -> PathBuf {
+        self.path.with_extension("idx")
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// Opens the pack file for reading entries
+    pub fn open_file(&self) -> io::Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(&self.path)?))
+    }
+
+    /// Reads the type and size of the entry at an offset.
+    /// The first byte holds 3 bits of type and the low 4 bits of the size;
+    /// the following bytes hold 7 more bits of the size each.
+    pub fn read_entry_header(
+        &self,
+        file: &mut BufReader<File>,
+        offset: u64,
+    ) -> io::Result<EntryHeader> {
+        file.seek(SeekFrom::Start(offset))?;
+        let [mut byte] = read_bytes(file)?;
+        let type_bits = (byte >> 4) & 0b111;
+        let mut size = (byte & 0b1111) as u64;
+        let mut shift = 4;
+        while byte & CONTINUATION_FLAG != 0 {
+            [byte] = read_bytes(file)?;
+            if shift > u64::BITS - 7 {
+                return Err(corrupt_pack(&self.path, offset, "entry size overflows"));
+            }
+            size |= ((byte & !CONTINUATION_FLAG) as u64) << shift;
+            shift += 7;
+        }
+
+        let kind = match type_bits {
+            1 => EntryKind::Base(ObjectType::Commit),
+            2 => EntryKind::Base(ObjectType::Tree),
+            3 => EntryKind::Base(ObjectType::Blob),
+            4 => EntryKind::Base(ObjectType::Tag),
+            6 => {
+                // The base's offset is given relative to this entry.
+                // Each continuation byte adds one before shifting, so that
+                // there is only one way to write each distance.
+                [byte] = read_bytes(file)?;
+                let mut distance = (byte & !CONTINUATION_FLAG) as u64;
+                while byte & CONTINUATION_FLAG != 0 {
+                    [byte] = read_bytes(file)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|distance| distance.checked_mul(1 << 7))
+                        .ok_or_else(|| {
+                            corrupt_pack(&self.path, offset, "delta offset overflows")
+                        })?
+                        | (byte & !CONTINUATION_FLAG) as u64;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance != 0)
+                    .ok_or_else(|| {
+                        corrupt_pack(&self.path, offset, "delta base is out of range")
+                    })?;
+                EntryKind::OffsetDelta { base_offset }
+            }
+            7 => EntryKind::RefDelta {
+                base: read_hash(file, self.index.format())?,
+            },
+            _ => return Err(corrupt_pack(&self.path, offset, "unknown entry type")),
+        };
+        let data_offset = file.stream_position()?;
+        Ok(EntryHeader {
+            kind,
+            size,
+            data_offset,
+        })
+    }
+
+    /// Walks the chain of deltas starting at the entry at an offset
+    pub fn delta_chain<'a>(&'a self, file: &'a mut BufReader<File>, offset: u64) -> DeltaChain<'a> {
+        DeltaChain {
+            pack: self,
+            file,
+            next: Some(Ok(offset)),
+            entries: 0,
+        }
+    }
+
+    /// Inflates an entry's data as it is read, rather than all at once.
+    /// The stream ends with the compressed data; checking that it
+    /// has `header.size` bytes is up to the reader.
+    pub fn stream_entry_data(
+        &self,
+        mut file: BufReader<File>,
+        header: &EntryHeader,
+    ) -> io::Result<ZlibDecoder<BufReader<File>>> {
+        file.seek(SeekFrom::Start(header.data_offset))?;
+        Ok(ZlibDecoder::new(file))
+    }
+
+    /// Reads the size of the object a delta entry rebuilds,
+    /// inflating only the start of the delta where the size is stored
+    pub fn read_delta_result_size(
+        &self,
+        file: &mut BufReader<File>,
+        header: &EntryHeader,
+    ) -> io::Result<u64> {
+        file.seek(SeekFrom::Start(header.data_offset))?;
+        let mut sizes = vec![];
+        ZlibDecoder::new(file)
+            .take(MAX_DELTA_SIZES_BYTES as u64)
+            .read_to_end(&mut sizes)?;
+        let (_, result_size) = delta_sizes(&sizes)
+            .map_err(|e| corrupt_pack(&self.path, header.data_offset, &e.to_string()))?;
+        Ok(result_size as u64)
+    }
+
+    /// Inflates an entry's data, which follows its header
+    pub fn read_entry_data(
+        &self,
+        file: &mut BufReader<File>,
+        header: &EntryHeader,
+    ) -> io::Result<Vec<u8>> {
+        file.seek(SeekFrom::Start(header.data_offset))?;
+        let mut data = Vec::with_capacity(header.size.min(MAX_RESERVED_ENTRY_BYTES) as usize);
+        // Inflating one byte more than the size is enough to tell it is wrong
+        ZlibDecoder::new(file)
+            .take(header.size.saturating_add(1))
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != header.size {
+            return Err(corrupt_pack(
+                &self.path,
+                header.data_offset,
+                "inflated data has the wrong size",
+            ));
+        }
+        Ok(data)
+    }
+}
+
+impl Repository {
+    /// The packs under `objects/pack`, each opened the first time it is needed
+    pub fn packs(&self) -> io::Result<Vec<&Pack>> {
+        self.pack_slots()?
+            .iter()
+            .map(|slot| self.open_pack(slot))
+            .collect()
+    }
+
+    /// The pack with the given `.idx` file name, as the multi-pack-index names it,
+    /// opening only that pack. Returns None if there is no such pack.
+    pub fn pack_named(&self, index_name: &str) -> io::Result<Option<&Pack>> {
+        let slots = self.pack_slots()?;
+        match slots.binary_search_by(|slot| slot.index_name.as_str().cmp(index_name)) {
+            Ok(position) => self.open_pack(&slots[position]).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn open_pack<'a>(&self, slot: &'a PackSlot) -> io::Result<&'a Pack> {
+        if let Some(pack) = slot.pack.get() {
+            return Ok(pack);
+        }
+        let pack = Pack::open(&slot.index_path, self.object_format())?;
+        Ok(slot.pack.get_or_init(|| pack))
+    }
+
+    // Lists the packs under `objects/pack`, sorted by name, without opening them
+    fn pack_slots(&self) -> io::Result<&[PackSlot]> {
+        if let Some(slots) = self.packs.get() {
+            return Ok(slots);
+        }
+
+        let mut index_paths = vec![];
+        match fs::read_dir(self.packs_dir()) {
+            Ok(entries) => {
+                for entry in entries {
+                    let path = entry?.path();
+                    if path.extension().is_some_and(|extension| extension == "idx") {
+                        index_paths.push(path);
+                    }
+                }
+            }
+            // A repository without any packs
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        index_paths.sort();
+
+        let slots = index_paths
+            .into_iter()
+            // An index without its pack is left over from an interrupted write
+            .filter(|index_path| index_path.with_extension("pack").is_file())
+            .filter_map(|index_path| {
+                let index_name = index_path.file_name()?.to_str()?.to_string();
+                Some(PackSlot {
+                    index_name,
+                    index_path,
+                    pack: OnceCell::new(),
+                })
+            })
+            .collect();
+        Ok(self.packs.get_or_init(|| slots))
+    }
+
+    /// Finds an object in the packs, returning its type and contents
+    /// (without a header), or None if no pack has it.
+    pub fn read_packed_object(&self, hash: Hash) -> io::Result<Option<(ObjectType, Vec<u8>)>> {
+        match self.find_packed_object(hash)? {
+            Some((pack, offset)) => self.read_pack_entry(pack, offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds which pack holds an object and its offset in that pack,
+    /// or returns None if no pack has it.
+    /// The multi-pack-index is checked first, opening only the pack it names.
+    /// Then the indexes of any packs it doesn't cover are searched.
+    pub fn find_packed_object(&self, hash: Hash) -> io::Result<Option<(&Pack, u64)>> {
+        let multi_pack_index = self.multi_pack_index();
+        if let Some((pack_name, offset)) =
+            multi_pack_index.and_then(|multi_pack_index| multi_pack_index.lookup(&hash))
+        {
+            // The pack may have been deleted since the multi-pack-index was written
+            if let Some(pack) = self.pack_named(pack_name)? {
+                return Ok(Some((pack, offset)));
+            }
+        }
+
+        for slot in self.pack_slots()? {
+            // The multi-pack-index has every object in the packs it covers
+            let covered = multi_pack_index.is_some_and(|multi_pack_index| {
+                multi_pack_index.pack_names().contains(&slot.index_name)
+            });
+            if covered {
+                continue;
+            }
+            let pack = self.open_pack(slot)?;
+            if let Some((offset, _)) = pack.index().lookup(&hash) {
+                return Ok(Some((pack, offset)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the type and size of the object at an offset in a pack
+    /// without inflating it. A delta's size is at the start of the delta,
+    /// but its type is the type of the whole object at the end of its chain of bases.
+    pub fn read_pack_entry_header(
+        &self,
+        pack: &Pack,
+        offset: u64,
+    ) -> io::Result<(ObjectType, u64)> {
+        let mut file = pack.open_file()?;
+        let mut chain = pack.delta_chain(&mut file, offset);
+        let size = match chain.next_link(offset)? {
+            ChainLink::Base {
+                header,
+                object_type,
+                ..
+            } => return Ok((object_type, header.size)),
+            ChainLink::Delta { header, .. } => {
+                pack.read_delta_result_size(chain.file(), &header)?
+            }
+            ChainLink::External(_) => unreachable!("a chain starts inside the pack"),
+        };
+        loop {
+            match chain.next_link(offset)? {
+                ChainLink::Base { object_type, .. } => return Ok((object_type, size)),
+                ChainLink::Delta { .. } => {}
+                ChainLink::External(base) => {
+                    let (object_type, _) = self.read_object_header(base)?;
+                    return Ok((object_type, size));
+                }
+            }
+        }
+    }
+
+    /// Reads the object at an offset in a pack, resolving any deltas.
+    /// The objects that deltas are applied to are kept in the delta base cache,
+    /// so other objects with deltas against them can be resolved quickly.
+    pub fn read_pack_entry(&self, pack: &Pack, offset: u64) -> io::Result<(ObjectType, Vec<u8>)> {
+        let pack_checksum = pack.index().pack_checksum();
+        let mut file = pack.open_file()?;
+
+        // Follow the chain of deltas back to a base object,
+        // stopping early if one of the objects on the way is cached.
+        // Keep the deltas and their offsets to apply on the way back.
+        let mut deltas = vec![];
+        let mut chain = pack.delta_chain(&mut file, offset);
+        // The offset of the base object, if it still needs to be cached
+        let mut uncached_offset = None;
+        let (object_type, mut contents): (ObjectType, Rc<[u8]>) = loop {
+            let cached = chain.next_offset().and_then(|offset| {
+                self.delta_base_cache
+                    .borrow_mut()
+                    .get((pack_checksum, offset))
+            });
+            if let Some(cached) = cached {
+                break cached;
+            }
+
+            match chain.next_link(offset)? {
+                ChainLink::Base {
+                    offset,
+                    header,
+                    object_type,
+                } => {
+                    let contents = pack.read_entry_data(chain.file(), &header)?;
+                    // An object that isn't a delta doesn't need the cache
+                    if deltas.is_empty() {
+                        return Ok((object_type, contents));
+                    }
+                    uncached_offset = Some(offset);
+                    break (object_type, contents.into());
+                }
+                ChainLink::Delta { offset, header } => {
+                    deltas.push((offset, pack.read_entry_data(chain.file(), &header)?));
+                }
+                ChainLink::External(base) => {
+                    let (object_type, contents) = self.read_object_contents(base)?;
+                    break (object_type, contents.into());
+                }
+            }
+        };
+
+        if let Some(offset) = uncached_offset {
+            self.delta_base_cache.borrow_mut().insert(
+                (pack_checksum, offset),
+                object_type,
+                contents.clone(),
+            );
+        }
+        // Apply the deltas, starting from the one closest to the base.
+        // Every result except the last is the base of the next delta.
+        while let Some((delta_offset, delta)) = deltas.pop() {
+            let result = apply_delta(&contents, &delta)
+                .map_err(|e| corrupt_pack(pack.path(), delta_offset, &e.to_string()))?;
+            if deltas.is_empty() {
+                return Ok((object_type, result));
+            }
+            contents = result.into();
+            self.delta_base_cache.borrow_mut().insert(
+                (pack_checksum, delta_offset),
+                object_type,
+                contents.clone(),
+            );
+        }
+        // The object itself was in the cache
+        Ok((object_type, contents.to_vec()))
+    }
+}
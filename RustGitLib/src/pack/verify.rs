@@ -0,0 +1,551 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::packfile::{ChainLink, EntryKind, Pack};
+use super::PackIndex;
+use crate::hash::{Hash, ObjectFormat, MAX_HASH_BYTES};
+use crate::object::{with_header, ObjectType};
+use crate::repository::Repository;
+
+/// Something wrong found while verifying a pack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackProblem {
+    /// The index has a header for a version other than 2
+    UnsupportedIndexVersion { version: u32 },
+    /// The index file is too short to hold its header, its fan-out table,
+    /// or the number of objects the table gives
+    IndexTruncated { length: u64, expected: u64 },
+    /// A fan-out entry, stored at `offset` in the index,
+    /// counts fewer objects than the entry for the bucket before it
+    FanoutNotMonotonic {
+        bucket: u8,
+        offset: u64,
+        objects: u32,
+        previous: u32,
+    },
+    /// An object's 4-byte offset, stored at `offset` in the index,
+    /// refers to an entry past the end of the 8-byte offset table
+    LargeOffsetOutOfRange { hash: Hash, offset: u64 },
+    /// A hash, stored at `offset` in the index,
+    /// is in the fan-out bucket for a different first byte
+    WrongBucket { hash: Hash, bucket: u8, offset: u64 },
+    /// A hash, stored at `offset` in the index,
+    /// is not greater than the hash before it in its bucket
+    Unsorted {
+        hash: Hash,
+        previous: Hash,
+        bucket: u8,
+        offset: u64,
+    },
+    /// The CRC32 of an object's packed data differs from the index
+    CrcMismatch {
+        hash: Hash,
+        offset: u64,
+        expected: u32,
+        found: u32,
+    },
+    /// An object's contents don't hash to the hash the index gives it
+    HashMismatch {
+        hash: Hash,
+        offset: u64,
+        found: Hash,
+    },
+    /// An object's contents look like part of a SHA-1 collision attack
+    CollisionAttackDetected { hash: Hash, offset: u64 },
+    /// An object could not be read or its deltas could not be applied
+    Unreadable {
+        hash: Hash,
+        offset: u64,
+        reason: String,
+    },
+    /// The checksum at the end of the pack doesn't match its contents
+    PackChecksumMismatch { expected: Hash, found: Hash },
+    /// The index was written for a pack with a different checksum
+    PackIndexMismatch { expected: Hash, found: Hash },
+    /// The checksum at the end of the index doesn't match its contents
+    IndexChecksumMismatch { expected: Hash, found: Hash },
+    /// The pack's data can't be parsed at an offset
+    Corrupt { offset: u64, reason: String },
+}
+
+impl Display for PackProblem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use PackProblem::*;
+
+        match self {
+            UnsupportedIndexVersion { version } => {
+                write!(f, "index version {} is not supported", version)
+            }
+            IndexTruncated { length, expected } => write!(
+                f,
+                "index is {} bytes, but should be at least {}",
+                length, expected
+            ),
+            FanoutNotMonotonic {
+                bucket,
+                offset,
+                objects,
+                previous,
+            } => write!(
+                f,
+                "fan-out entry for {:02x} at offset {} is {}, less than the previous {}",
+                bucket, offset, objects, previous
+            ),
+            LargeOffsetOutOfRange { hash, offset } => write!(
+                f,
+                "{} has a large offset at index offset {} past the end of the table",
+                hash, offset
+            ),
+            WrongBucket {
+                hash,
+                bucket,
+                offset,
+            } => write!(
+                f,
+                "{} at index offset {} is in the fan-out bucket for {:02x}",
+                hash, offset, bucket
+            ),
+            Unsorted {
+                hash,
+                previous,
+                bucket,
+                offset,
+            } => write!(
+                f,
+                "{} at index offset {} in fan-out bucket {:02x} is not sorted after {}",
+                hash, offset, bucket, previous
+            ),
+            CrcMismatch {
+                hash,
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} at offset {} has CRC32 {:08x}, expected {:08x}",
+                hash, offset, found, expected
+            ),
+            HashMismatch {
+                hash,
+                offset,
+                found,
+            } => write!(f, "{} at offset {} hashes to {}", hash, offset, found),
+            CollisionAttackDetected { hash, offset } => write!(
+                f,
+                "{} at offset {} appears to be part of a SHA-1 collision attack",
+                hash, offset
+            ),
+            Unreadable {
+                hash,
+                offset,
+                reason,
+            } => write!(f, "{} at offset {} can't be read: {}", hash, offset, reason),
+            PackChecksumMismatch { expected, found } => {
+                write!(f, "pack checksum is {}, expected {}", found, expected)
+            }
+            PackIndexMismatch { expected, found } => {
+                write!(f, "index is for pack {}, but pack is {}", expected, found)
+            }
+            IndexChecksumMismatch { expected, found } => {
+                write!(f, "index checksum is {}, expected {}", found, expected)
+            }
+            Corrupt { offset, reason } => write!(f, "{} at offset {}", reason, offset),
+        }
+    }
+}
+
+/// What `git verify-pack -v` shows for each object
+#[derive(Debug, Clone)]
+pub struct ObjectReport {
+    pub hash: Hash,
+    /// The type of the object, after resolving any deltas
+    pub object_type: ObjectType,
+    /// The size of the entry's inflated data. For deltas, this is the delta's size.
+    pub size: u64,
+    /// The number of bytes the entry takes up in the pack, including its header
+    pub size_in_pack: u64,
+    pub offset: u64,
+    /// The number of deltas between this object and a whole object
+    pub depth: usize,
+    /// The object this one is a delta against
+    pub base: Option<Hash>,
+}
+
+/// The result of checking a pack and its index
+#[derive(Debug, Clone, Default)]
+pub struct PackReport {
+    /// Every object in the pack, in the order they are stored
+    pub objects: Vec<ObjectReport>,
+    pub problems: Vec<PackProblem>,
+}
+
+impl PackReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The number of objects with each delta chain length.
+    /// Objects that aren't deltas have a chain length of 0.
+    pub fn chain_lengths(&self) -> BTreeMap<usize, usize> {
+        let mut chain_lengths = BTreeMap::new();
+        for object in &self.objects {
+            *chain_lengths.entry(object.depth).or_default() += 1;
+        }
+        chain_lengths
+    }
+}
+
+/// Checks that every hash in an index is in the right fan-out bucket
+/// and that the hashes are sorted
+pub fn check_fanout(index: &PackIndex) -> Vec<PackProblem> {
+    let mut problems = vec![];
+    let mut hashes = index.hashes();
+    let mut position = 0;
+    let mut previous_objects = 0;
+    for (first_byte, &objects) in index.fanout().iter().enumerate() {
+        // The difference in the cumulative number of objects
+        // is the number of objects with this first byte
+        let bucket_size = objects.saturating_sub(previous_objects) as usize;
+        let (bucket, hashes_rest) = hashes.split_at(bucket_size.min(hashes.len()));
+        let mut previous_hash = None;
+        for &hash in bucket {
+            let offset = index.hash_offset(position);
+            // We already know the first byte of the hash, so ensure it matches
+            if hash.first_byte() != first_byte as u8 {
+                problems.push(PackProblem::WrongBucket {
+                    hash,
+                    bucket: first_byte as u8,
+                    offset,
+                });
+            }
+            if let Some(previous) = previous_hash {
+                if hash <= previous {
+                    problems.push(PackProblem::Unsorted {
+                        hash,
+                        previous,
+                        bucket: first_byte as u8,
+                        offset,
+                    });
+                }
+            }
+            previous_hash = Some(hash);
+            position += 1;
+        }
+        hashes = hashes_rest;
+        previous_objects = objects;
+    }
+    problems
+}
+
+// Hashes a file, except for the checksum at the end,
+// and returns the computed and stored checksums
+fn file_checksums(path: &Path, format: ObjectFormat) -> io::Result<(Hash, Hash)> {
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let contents_length = length.saturating_sub(format.hash_bytes() as u64);
+    let mut hasher = format.hasher();
+    io::copy(&mut (&mut file).take(contents_length), &mut hasher)?;
+    let mut stored = [0; MAX_HASH_BYTES];
+    let stored = &mut stored[..format.hash_bytes()];
+    file.read_exact(stored)?;
+    Ok((hasher.finalize(), Hash::new(format, stored)))
+}
+
+/// Checks an index without its pack: the hashes are sorted into
+/// the right fan-out buckets and the checksum at the end of the file is right.
+/// `path` is the `.idx` file the index was read from.
+pub fn check_index(index: &PackIndex, path: &Path) -> io::Result<Vec<PackProblem>> {
+    let mut problems = check_fanout(index);
+    let (index_checksum, stored_index_checksum) = file_checksums(path, index.format())?;
+    if index_checksum != stored_index_checksum {
+        problems.push(PackProblem::IndexChecksumMismatch {
+            expected: stored_index_checksum,
+            found: index_checksum,
+        });
+    }
+    Ok(problems)
+}
+
+// Reads the raw bytes of an entry for computing its CRC32
+fn entry_crc32(file: &mut File, offset: u64, size_in_pack: u64) -> io::Result<u32> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut entry = file.take(size_in_pack);
+    let mut buffer = [0; 1 << 16];
+    loop {
+        let read = entry.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+impl Repository {
+    /// Checks a pack and its index like `git verify-pack`:
+    /// the index's hashes are sorted into the right fan-out buckets,
+    /// each object's packed data matches the CRC32 in the index,
+    /// each object (with deltas resolved) matches its hash,
+    /// and the checksums at the end of both files are right.
+    /// Problems with the pack's contents go in the report;
+    /// only failures to read the files are returned as errors.
+    pub fn verify_pack(&self, pack: &Pack) -> io::Result<PackReport> {
+        let index = pack.index();
+        let mut report = PackReport {
+            objects: vec![],
+            problems: check_index(index, &pack.index_path())?,
+        };
+
+        let (pack_checksum, stored_pack_checksum) = file_checksums(pack.path(), index.format())?;
+        if pack_checksum != stored_pack_checksum {
+            report.problems.push(PackProblem::PackChecksumMismatch {
+                expected: stored_pack_checksum,
+                found: pack_checksum,
+            });
+        }
+        if index.pack_checksum() != stored_pack_checksum {
+            report.problems.push(PackProblem::PackIndexMismatch {
+                expected: index.pack_checksum(),
+                found: stored_pack_checksum,
+            });
+        }
+
+        // Each entry runs up to the start of the next one,
+        // and the last one runs up to the checksum
+        let mut positions: Vec<usize> = (0..index.len()).collect();
+        positions.sort_by_key(|&position| index.offset(position));
+        let pack_end = pack
+            .path()
+            .metadata()?
+            .len()
+            .saturating_sub(index.format().hash_bytes() as u64);
+        let hashes_by_offset: HashMap<u64, Hash> = positions
+            .iter()
+            .map(|&position| (index.offset(position), index.hashes()[position]))
+            .collect();
+        let mut depths: HashMap<u64, usize> = HashMap::new();
+
+        let mut raw_file = File::open(pack.path())?;
+        let mut file = pack.open_file()?;
+        for (order, &position) in positions.iter().enumerate() {
+            let hash = index.hashes()[position];
+            let offset = index.offset(position);
+            let end = match positions.get(order + 1) {
+                Some(&next) => index.offset(next),
+                None => pack_end,
+            };
+            let size_in_pack = end.saturating_sub(offset);
+
+            if let Some(expected) = index.crc32(position) {
+                let found = entry_crc32(&mut raw_file, offset, size_in_pack)?;
+                if found != expected {
+                    report.problems.push(PackProblem::CrcMismatch {
+                        hash,
+                        offset,
+                        expected,
+                        found,
+                    });
+                }
+            }
+
+            let unreadable = |reason: io::Error| PackProblem::Unreadable {
+                hash,
+                offset,
+                reason: reason.to_string(),
+            };
+            let header = match pack.read_entry_header(&mut file, offset) {
+                Ok(header) => header,
+                Err(e) => {
+                    report.problems.push(unreadable(e));
+                    continue;
+                }
+            };
+            let (object_type, contents) = match self.read_pack_entry(pack, offset) {
+                Ok(object) => object,
+                Err(e) => {
+                    report.problems.push(unreadable(e));
+                    continue;
+                }
+            };
+            let mut hasher = index.format().hasher();
+            hasher.update(&with_header(object_type, &contents));
+            if hasher.clone().try_finalize().is_err() {
+                report
+                    .problems
+                    .push(PackProblem::CollisionAttackDetected { hash, offset });
+            }
+            let found = hasher.finalize();
+            if found != hash {
+                report.problems.push(PackProblem::HashMismatch {
+                    hash,
+                    offset,
+                    found,
+                });
+            }
+
+            let base = match header.kind {
+                EntryKind::Base(_) => None,
+                EntryKind::OffsetDelta { base_offset } => {
+                    hashes_by_offset.get(&base_offset).copied()
+                }
+                EntryKind::RefDelta { base } => Some(base),
+            };
+            let depth = match self.delta_depth(pack, &mut file, offset, &mut depths) {
+                Ok(depth) => depth,
+                Err(e) => {
+                    report.problems.push(unreadable(e));
+                    continue;
+                }
+            };
+            report.objects.push(ObjectReport {
+                hash,
+                object_type,
+                size: header.size,
+                size_in_pack,
+                offset,
+                depth,
+                base,
+            });
+        }
+        Ok(report)
+    }
+
+    // Counts the deltas between the entry at an offset and a whole object,
+    // remembering the depths already worked out
+    fn delta_depth(
+        &self,
+        pack: &Pack,
+        file: &mut BufReader<File>,
+        offset: u64,
+        depths: &mut HashMap<u64, usize>,
+    ) -> io::Result<usize> {
+        // Walk back to a whole object or an entry whose depth we know,
+        // keeping the offsets of the deltas on the way
+        let mut deltas = vec![];
+        let mut chain = pack.delta_chain(file, offset);
+        let base_depth = loop {
+            if let Some(&depth) = chain.next_offset().and_then(|offset| depths.get(&offset)) {
+                break depth;
+            }
+            match chain.next_link(offset)? {
+                ChainLink::Base { offset, .. } => {
+                    depths.insert(offset, 0);
+                    break 0;
+                }
+                ChainLink::Delta { offset, .. } => deltas.push(offset),
+                // A base outside this pack counts as a whole object
+                ChainLink::External(_) => break 0,
+            }
+        };
+        // Each delta is one deeper than its base
+        for (depth, &delta_offset) in (base_depth + 1..).zip(deltas.iter().rev()) {
+            depths.insert(delta_offset, depth);
+        }
+        Ok(depths[&offset])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::pack::packfile::corrupt_pack;
+    use crate::test_repo::{TestEntry, TestRepo};
+
+    const BLOB1: &[u8] = b"first blob\n";
+    const BLOB2: &[u8] = b"second blob, which is a little longer\n";
+
+    fn verify(test_repo: &TestRepo, index_path: &Path) -> PackReport {
+        let repository = test_repo.open();
+        let pack = Pack::open(index_path, repository.object_format()).unwrap();
+        repository.verify_pack(&pack).unwrap()
+    }
+
+    #[test]
+    fn good_pack_has_no_problems() {
+        let test_repo = TestRepo::new();
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, BLOB1), (ObjectType::Blob, BLOB2)]);
+        let report = verify(&test_repo, &pack.index_path);
+        assert_eq!(report.problems, vec![]);
+        assert_eq!(report.objects.len(), 2);
+    }
+
+    #[test]
+    fn truncated_pack_is_reported() {
+        let test_repo = TestRepo::new();
+        let pack = test_repo.write_pack(&[(ObjectType::Blob, BLOB1), (ObjectType::Blob, BLOB2)]);
+        // Cut the pack off partway through the second entry's data
+        let (hash, offset) = pack.objects[1];
+        let contents = fs::read(&pack.pack_path).unwrap();
+        fs::write(&pack.pack_path, &contents[..offset as usize + 4]).unwrap();
+
+        let report = verify(&test_repo, &pack.index_path);
+        assert!(report
+            .problems
+            .iter()
+            .any(|problem| matches!(problem, PackProblem::PackChecksumMismatch { .. })));
+        assert!(report.problems.iter().any(|problem| matches!(
+            problem,
+            PackProblem::Unreadable { hash: found, .. } if *found == hash
+        )));
+        assert_eq!(report.objects.len(), 1);
+    }
+
+    #[test]
+    fn delta_cycle_is_reported_the_same_everywhere() {
+        let test_repo = TestRepo::new();
+        // A REF_DELTA against itself
+        let pack = test_repo.write_pack_entries(&[TestEntry::RefDelta {
+            base: ObjectFormat::Sha1.digest(&with_header(ObjectType::Blob, BLOB1)),
+            delta: b"\x0b\x0b",
+            object: (ObjectType::Blob, BLOB1),
+        }]);
+        let (hash, offset) = pack.objects[0];
+
+        let cycle = corrupt_pack(&pack.pack_path, offset, "delta chain has a cycle").to_string();
+        let repository = test_repo.open();
+        let error = repository.read_object_header(hash).unwrap_err();
+        assert_eq!(error.to_string(), cycle);
+        let error = repository.read_object_contents(hash).unwrap_err();
+        assert_eq!(error.to_string(), cycle);
+        let report = verify(&test_repo, &pack.index_path);
+        assert!(report.problems.contains(&PackProblem::Unreadable {
+            hash,
+            offset,
+            reason: cycle,
+        }));
+    }
+
+    #[test]
+    fn oversized_entry_is_reported() {
+        let test_repo = TestRepo::new();
+        // The second entry claims to inflate to about 2^57 bytes
+        let pack = test_repo.write_pack_with_sizes(&[
+            (ObjectType::Blob, BLOB1, BLOB1.len() as u64),
+            (ObjectType::Blob, BLOB2, (1 << 57) - 1),
+        ]);
+        let (hash, offset) = pack.objects[1];
+        // The size takes 8 bytes after the type byte
+        let data_offset = offset + 9;
+
+        let report = verify(&test_repo, &pack.index_path);
+        assert_eq!(
+            report.problems,
+            vec![PackProblem::Unreadable {
+                hash,
+                offset,
+                reason: corrupt_pack(
+                    &pack.pack_path,
+                    data_offset,
+                    "inflated data has the wrong size"
+                )
+                .to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+// Some helper functions for parsing objects
+
+use crate::error::ParseError;
+
+fn decimal_char_value(decimal_char: u8) -> Option<u8> {
+    match decimal_char {
+        b'0'..=b'9' => Some(decimal_char - b'0'),
+        _ => None,
+    }
+}
+
+// Parses a decimal string, e.g. "123", into its value, e.g. 123.
+// Returns None if any characters are invalid or the value overflows a usize.
+pub fn parse_decimal(decimal_str: &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for &decimal_char in decimal_str {
+        let char_value = decimal_char_value(decimal_char)?;
+        value = value.checked_mul(10)?;
+        value = value.checked_add(char_value as usize)?;
+    }
+    Some(value)
+}
+
+// Like str::split_once(), split the slice at the next delimiter
+pub fn split_once<T: PartialEq>(slice: &[T], delimiter: T) -> Option<(&[T], &[T])> {
+    let index = slice.iter().position(|element| *element == delimiter)?;
+    Some((&slice[..index], &slice[index + 1..]))
+}
+
+// Reads through an object's bytes from the start, keeping track of the offset
+// so that parse errors can say where they happened
+pub struct Cursor<'a> {
+    object: &'a [u8],
+    rest: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(object: &'a [u8]) -> Self {
+        Cursor {
+            object,
+            rest: object,
+        }
+    }
+
+    // How many bytes have been read so far
+    pub fn offset(&self) -> usize {
+        self.object.len() - self.rest.len()
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        self.rest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    // An error at the current offset
+    pub fn error(&self, reason: impl Into<String>) -> ParseError {
+        ParseError::new(self.offset(), reason)
+    }
+
+    // Skips over a prefix if the remaining bytes start with it
+    pub fn strip_prefix(&mut self, prefix: &[u8]) -> bool {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Takes the bytes up to the next delimiter, skipping over the delimiter
+    pub fn take_until(&mut self, delimiter: u8, reason: &str) -> Result<&'a [u8], ParseError> {
+        let (taken, rest) = split_once(self.rest, delimiter).ok_or_else(|| self.error(reason))?;
+        self.rest = rest;
+        Ok(taken)
+    }
+
+    // Takes the next `length` bytes
+    pub fn take(&mut self, length: usize, reason: &str) -> Result<&'a [u8], ParseError> {
+        let taken = self.rest.get(..length).ok_or_else(|| self.error(reason))?;
+        self.rest = &self.rest[length..];
+        Ok(taken)
+    }
+}
@@ -0,0 +1,458 @@
+use std::cell::OnceCell;
+use std::collections::BTreeSet;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::RustGitError;
+use crate::hash::Hash;
+use crate::repository::Repository;
+
+pub mod info;
+pub mod packed;
+
+pub use info::{InfoRef, InfoRefs};
+pub use packed::{PackedRef, PackedRefs};
+
+const REF_PREFIX: &str = "ref: refs/heads/";
+// A ref file starting with this names another ref instead of holding a hash
+const SYMREF_PREFIX: &str = "ref: ";
+
+/// Git reads at most this many refs when following a chain of symbolic refs
+pub const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Why a ref couldn't be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefError {
+    /// No ref has this name
+    NotFound(String),
+    /// A symbolic ref points to a ref that doesn't exist
+    Dangling { symref: String, target: String },
+    /// Following symbolic refs led back to one already seen.
+    /// Holds the chain of names, ending with the repeated one.
+    Circular(Vec<String>),
+    /// The chain of symbolic refs starting at this ref is too long
+    TooDeep(String),
+    /// The name can't be a ref, e.g. it leaves the git directory
+    InvalidName(String),
+    /// The ref holds neither a hash nor a symbolic ref
+    Malformed { name: String, contents: String },
+    /// A line of a file listing refs, e.g. `packed-refs`, can't be parsed
+    MalformedLine {
+        file: &'static str,
+        line_number: usize,
+        line: String,
+    },
+    /// A file listing refs, e.g. `info/refs`, doesn't exist
+    MissingFile(PathBuf),
+}
+
+impl Display for RefError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use RefError::*;
+
+        match self {
+            NotFound(name) => write!(f, "Ref {} not found", name),
+            Dangling { symref, target } => write!(
+                f,
+                "Symbolic ref {} points to missing ref {}",
+                symref, target
+            ),
+            Circular(chain) => write!(f, "Symbolic refs are circular: {}", chain.join(" -> ")),
+            TooDeep(name) => write!(
+                f,
+                "Symbolic ref {} is nested more than {} deep",
+                name, MAX_SYMREF_DEPTH
+            ),
+            InvalidName(name) => write!(f, "Invalid ref name: {}", name),
+            Malformed { name, contents } => write!(f, "Malformed ref {}: {}", name, contents),
+            MalformedLine {
+                file,
+                line_number,
+                line,
+            } => write!(f, "Malformed {} line {}: {}", file, line_number, line),
+            MissingFile(path) => write!(f, "{} does not exist", path.display()),
+        }
+    }
+}
+
+impl error::Error for RefError {}
+
+impl From<RefError> for Error {
+    fn from(ref_error: RefError) -> Error {
+        RustGitError::InvalidRef(ref_error).into()
+    }
+}
+
+/// What a ref ends up pointing to, after following any symbolic refs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolvedRef {
+    /// The last ref in the chain, which holds a hash
+    Hash { name: String, hash: Hash },
+    /// The chain ends at a branch with no commits yet,
+    /// like HEAD in a new repository
+    Unborn { name: String },
+}
+
+// Rejects names that would read files outside the refs,
+// e.g. `../config` or `/etc/passwd`
+fn check_ref_name(name: &str) -> Result<(), RefError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('/')
+        && !name.contains('\0')
+        && name
+            .split('/')
+            .all(|component| !component.is_empty() && component != "." && component != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(RefError::InvalidName(name.to_string()))
+    }
+}
+
+/// The head is either at a specific commit or a named branch
+#[derive(Debug)]
+pub enum Head {
+    Commit(Hash),
+    Branch(String),
+}
+
+impl Head {
+    /// The commit the head is at. A branch is followed through any symbolic refs,
+    /// and one with no commits yet fails with `RustGitError::UnbornBranch`.
+    pub fn get_hash(&self, repository: &Repository) -> io::Result<Hash> {
+        use Head::*;
+
+        match self {
+            Commit(hash) => Ok(*hash),
+            Branch(branch) => match repository.get_branch_head(branch) {
+                Ok(hash_contents) => Hash::from_str(&hash_contents),
+                // The head is on a branch that doesn't exist yet
+                Err(e) if is_missing_ref(&e) => {
+                    Err(RustGitError::UnbornBranch(branch.clone()).into())
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+}
+
+// Whether an error is for a ref that doesn't exist at all,
+// rather than one that can't be resolved
+fn is_missing_ref(error: &Error) -> bool {
+    matches!(
+        RustGitError::from_io_error(error),
+        Some(RustGitError::InvalidRef(RefError::NotFound(_)))
+    )
+}
+
+// Adds the names of the loose refs under a directory, e.g. `refs/tags/v1.0`
+fn collect_loose_refs(dir: &Path, prefix: &str, names: &mut BTreeSet<String>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_loose_refs(&entry.path(), &name, names)?;
+        } else {
+            names.insert(name);
+        }
+    }
+    Ok(())
+}
+
+impl Repository {
+    pub fn get_head(&self) -> io::Result<Head> {
+        use Head::*;
+
+        let hash_contents = fs::read_to_string(self.head_file())?;
+        // Remove trailing newline
+        let hash_contents = hash_contents.trim_end();
+        // If .git/HEAD starts with `ref: refs/heads/`, it's a branch name.
+        // If it points to some other ref, follow it to a commit.
+        // Otherwise, it should be a commit hash.
+        Ok(match hash_contents.strip_prefix(REF_PREFIX) {
+            Some(branch) => Branch(branch.to_string()),
+            _ if hash_contents.starts_with(SYMREF_PREFIX) => match self.resolve_ref("HEAD")? {
+                ResolvedRef::Hash { hash, .. } => Commit(hash),
+                ResolvedRef::Unborn { name } => {
+                    Branch(name.trim_start_matches("refs/heads/").to_string())
+                }
+            },
+            _ => {
+                let hash = Hash::from_str(hash_contents)?;
+                Commit(hash)
+            }
+        })
+    }
+
+    /// Follows a ref through any symbolic refs (e.g. HEAD -> refs/heads/main)
+    /// to the hash it ends at. Gives up after `MAX_SYMREF_DEPTH` refs, like git.
+    /// A chain ending at a missing branch is an unborn branch,
+    /// but a chain ending at any other missing ref is dangling.
+    pub fn resolve_ref(&self, name: &str) -> io::Result<ResolvedRef> {
+        self.resolve_ref_in(name, &OnceCell::new())
+    }
+
+    // Resolves a ref like `resolve_ref()`, sharing `packed-refs`
+    // with other lookups once it has been read
+    fn resolve_ref_in(
+        &self,
+        name: &str,
+        packed_refs: &OnceCell<PackedRefs>,
+    ) -> io::Result<ResolvedRef> {
+        let mut chain = vec![name.to_string()];
+        loop {
+            let current = &chain[chain.len() - 1];
+            check_ref_name(current)?;
+            let Some(contents) = self.read_ref_in(current, packed_refs)? else {
+                let error = match chain.len() {
+                    1 => RefError::NotFound(current.clone()),
+                    _ if current.starts_with("refs/heads/") => {
+                        return Ok(ResolvedRef::Unborn {
+                            name: current.clone(),
+                        });
+                    }
+                    _ => RefError::Dangling {
+                        symref: chain[chain.len() - 2].clone(),
+                        target: current.clone(),
+                    },
+                };
+                return Err(error.into());
+            };
+
+            let Some(target) = contents.strip_prefix(SYMREF_PREFIX) else {
+                let hash = Hash::from_str(&contents).map_err(|_| RefError::Malformed {
+                    name: current.clone(),
+                    contents: contents.clone(),
+                })?;
+                return Ok(ResolvedRef::Hash {
+                    name: current.clone(),
+                    hash,
+                });
+            };
+            let target = target.trim().to_string();
+            if chain.contains(&target) {
+                chain.push(target);
+                return Err(RefError::Circular(chain).into());
+            }
+            if chain.len() >= MAX_SYMREF_DEPTH {
+                return Err(RefError::TooDeep(name.to_string()).into());
+            }
+            chain.push(target);
+        }
+    }
+
+    /// Lists every ref under `refs/`, loose or packed, sorted by name,
+    /// with the hash it resolves to.
+    /// Refs that don't resolve to a hash, like dangling symbolic refs, are skipped.
+    pub fn list_refs(&self) -> io::Result<Vec<(String, Hash)>> {
+        let mut names = BTreeSet::new();
+        collect_loose_refs(&self.common_dir().join("refs"), "refs", &mut names)?;
+        // Read `packed-refs` once, rather than again for each ref
+        let packed_refs = OnceCell::from(self.read_packed_refs()?);
+        names.extend(
+            packed_refs
+                .get()
+                .unwrap()
+                .refs()
+                .iter()
+                .map(|packed_ref| packed_ref.name.clone()),
+        );
+
+        let mut refs = vec![];
+        for name in names {
+            if let Ok(ResolvedRef::Hash { hash, .. }) = self.resolve_ref_in(&name, &packed_refs) {
+                refs.push((name, hash));
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Reads the `packed-refs` file
+    pub fn read_packed_refs(&self) -> io::Result<PackedRefs> {
+        PackedRefs::read(&self.packed_refs_file())
+    }
+
+    /// Reads a ref by its full name, e.g. `refs/heads/main`.
+    /// A ref's own file takes precedence over its entry in `packed-refs`,
+    /// since git updates a packed ref by writing a new file for it.
+    /// Returns the hash, or for a symbolic ref "ref: " and its target,
+    /// or None if the ref doesn't exist.
+    pub fn read_ref(&self, name: &str) -> io::Result<Option<String>> {
+        self.read_ref_in(name, &OnceCell::new())
+    }
+
+    // Reads a ref like `read_ref()`. `packed-refs` is only read
+    // if the ref has no file of its own and it hasn't been read already.
+    fn read_ref_in(
+        &self,
+        name: &str,
+        packed_refs: &OnceCell<PackedRefs>,
+    ) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.ref_file(name)) {
+            Ok(contents) => return Ok(Some(contents.trim_end().to_string())),
+            // A directory is a ref namespace, e.g. `refs/heads/feature/`
+            Err(e) if e.kind() == ErrorKind::NotFound || self.ref_file(name).is_dir() => {}
+            Err(e) => return Err(e),
+        }
+        if packed_refs.get().is_none() {
+            let _ = packed_refs.set(self.read_packed_refs()?);
+        }
+        let packed_refs = packed_refs.get().unwrap();
+        Ok(packed_refs
+            .get(name)
+            .map(|packed_ref| packed_ref.hash.to_string()))
+    }
+
+    /// Resolves a branch to its hash, from its own file or `packed-refs`,
+    /// falling back to `info/refs` if neither has the branch.
+    /// A branch that is a symbolic ref to a branch with no commits yet
+    /// fails with `RustGitError::UnbornBranch`.
+    pub fn get_branch_head(&self, branch: &str) -> io::Result<String> {
+        let name = format!("refs/heads/{}", branch);
+        match self.resolve_ref(&name) {
+            Ok(ResolvedRef::Hash { hash, .. }) => Ok(hash.to_string()),
+            Ok(ResolvedRef::Unborn { name: target }) => match self.read_from_info(&target)? {
+                Some(hash) => Ok(hash.to_string()),
+                None => {
+                    let target = target.trim_start_matches("refs/heads/").to_string();
+                    Err(RustGitError::UnbornBranch(target).into())
+                }
+            },
+            Err(e) if is_missing_ref(&e) => match self.read_from_info(&name)? {
+                Some(hash) => Ok(hash.to_string()),
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // Looks a ref up in `info/refs`, if the repository has one
+    fn read_from_info(&self, name: &str) -> io::Result<Option<Hash>> {
+        if !self.info_refs_file().is_file() {
+            return Ok(None);
+        }
+        let info_refs = InfoRefs::read(&self.info_refs_file())?;
+        Ok(info_refs.get(name).map(|info_ref| info_ref.hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    const PACKED: &str = "1111111111111111111111111111111111111111";
+    const LOOSE: &str = "2222222222222222222222222222222222222222";
+
+    #[test]
+    fn list_refs_reads_loose_and_packed_refs() {
+        let test_repo = TestRepo::new();
+        test_repo.write(
+            "packed-refs",
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {PACKED} refs/heads/main\n\
+                 {PACKED} refs/tags/v1\n"
+            ),
+        );
+        // A loose ref takes the place of a packed one with the same name
+        test_repo.write("refs/tags/v1", format!("{LOOSE}\n"));
+        test_repo.write("refs/heads/topic", "ref: refs/heads/main\n");
+        let repository = test_repo.open();
+
+        let refs: Vec<_> = repository
+            .list_refs()
+            .unwrap()
+            .into_iter()
+            .map(|(name, hash)| (name, hash.to_string()))
+            .collect();
+        assert_eq!(
+            refs,
+            [
+                ("refs/heads/main".to_string(), PACKED.to_string()),
+                ("refs/heads/topic".to_string(), PACKED.to_string()),
+                ("refs/tags/v1".to_string(), LOOSE.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn head_on_an_unborn_branch_has_no_commits_yet() {
+        let test_repo = TestRepo::new();
+        let repository = test_repo.open();
+        let head = repository.get_head().unwrap();
+        assert!(matches!(&head, Head::Branch(branch) if branch == "main"));
+
+        let error = head.get_hash(&repository).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::UnbornBranch("main".to_string()))
+        );
+
+        test_repo.write("refs/heads/main", format!("{LOOSE}\n"));
+        assert_eq!(head.get_hash(&repository).unwrap().to_string(), LOOSE);
+    }
+
+    #[test]
+    fn head_follows_symbolic_branches() {
+        let test_repo = TestRepo::new();
+        test_repo.write("HEAD", "ref: refs/heads/alias\n");
+        test_repo.write("refs/heads/alias", "ref: refs/heads/main\n");
+        let repository = test_repo.open();
+        let head = repository.get_head().unwrap();
+
+        let error = head.get_hash(&repository).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::UnbornBranch("main".to_string()))
+        );
+
+        test_repo.write(
+            "packed-refs",
+            format!("# pack-refs with: peeled fully-peeled sorted \n{PACKED} refs/heads/main\n"),
+        );
+        assert_eq!(head.get_hash(&repository).unwrap().to_string(), PACKED);
+    }
+
+    #[test]
+    fn branch_head_is_the_named_branch_not_head() {
+        let test_repo = TestRepo::new();
+        test_repo.write("refs/heads/main", format!("{LOOSE}\n"));
+        test_repo.write(
+            "packed-refs",
+            format!("# pack-refs with: peeled fully-peeled sorted \n{PACKED} refs/heads/feature\n"),
+        );
+        let repository = test_repo.open();
+
+        let feature = Head::Branch("feature".to_string());
+        assert_eq!(feature.get_hash(&repository).unwrap().to_string(), PACKED);
+        let head = repository.get_head().unwrap();
+        assert_eq!(head.get_hash(&repository).unwrap().to_string(), LOOSE);
+
+        let error = Head::Branch("missing".to_string())
+            .get_hash(&repository)
+            .unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::UnbornBranch("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn branch_head_falls_back_to_info_refs() {
+        let test_repo = TestRepo::new();
+        test_repo.write("info/refs", format!("{PACKED}\trefs/heads/main\n"));
+        let repository = test_repo.open();
+        let head = repository.get_head().unwrap();
+        assert_eq!(head.get_hash(&repository).unwrap().to_string(), PACKED);
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use super::RefError;
+use crate::hash::{hex_to_hash, Hash};
+
+// A ref name ending in this gives the peeled value of the ref before it
+const PEELED_SUFFIX: &str = "^{}";
+
+/// A ref listed in `info/refs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoRef {
+    pub name: String,
+    pub hash: Hash,
+    /// For an annotated tag, the object the tag chain ends at
+    pub peeled: Option<Hash>,
+}
+
+/// The contents of an `info/refs` file, written by `git update-server-info`
+/// so that clients fetching over "dumb" HTTP can find the refs
+#[derive(Debug, Clone, Default)]
+pub struct InfoRefs {
+    refs: Vec<InfoRef>,
+}
+
+fn malformed_line(line_number: usize, line: &str) -> Error {
+    RefError::MalformedLine {
+        file: "info/refs",
+        line_number,
+        line: line.to_string(),
+    }
+    .into()
+}
+
+impl InfoRefs {
+    pub fn read(path: &Path) -> io::Result<InfoRefs> {
+        let contents = fs::read_to_string(path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => RefError::MissingFile(path.to_path_buf()).into(),
+            _ => e,
+        })?;
+        InfoRefs::parse(&contents)
+    }
+
+    /// Parses the contents of an `info/refs` file.
+    /// Each line is "<hash>\t<refname>". An annotated tag is followed by
+    /// a line for "<refname>^{}" with the hash of the object it peels to.
+    pub fn parse(contents: &str) -> io::Result<InfoRefs> {
+        let mut refs: Vec<InfoRef> = vec![];
+        for (line_index, line) in contents.lines().enumerate() {
+            let line_number = line_index + 1;
+            let (hash, name) = line
+                .split_once('\t')
+                .ok_or_else(|| malformed_line(line_number, line))?;
+            let hash =
+                hex_to_hash(hash.as_bytes()).ok_or_else(|| malformed_line(line_number, line))?;
+            if name.is_empty() {
+                return Err(malformed_line(line_number, line));
+            }
+
+            match name.strip_suffix(PEELED_SUFFIX) {
+                Some(peeled_name) => {
+                    let info_ref = refs
+                        .last_mut()
+                        .filter(|info_ref| {
+                            info_ref.name == peeled_name && info_ref.peeled.is_none()
+                        })
+                        .ok_or_else(|| malformed_line(line_number, line))?;
+                    info_ref.peeled = Some(hash);
+                }
+                None => refs.push(InfoRef {
+                    name: name.to_string(),
+                    hash,
+                    peeled: None,
+                }),
+            }
+        }
+        Ok(InfoRefs { refs })
+    }
+
+    /// All the refs, in the order they are listed
+    pub fn refs(&self) -> &[InfoRef] {
+        &self.refs
+    }
+
+    /// Finds a ref by its exact full name, e.g. `refs/heads/main`
+    pub fn get(&self, name: &str) -> Option<&InfoRef> {
+        self.refs.iter().find(|info_ref| info_ref.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RustGitError;
+
+    const TAG: &str = "1111111111111111111111111111111111111111";
+    const COMMIT: &str = "2222222222222222222222222222222222222222";
+    const BRANCH: &str = "3333333333333333333333333333333333333333";
+
+    fn hash(hex_hash: &str) -> Hash {
+        hex_to_hash(hex_hash.as_bytes()).unwrap()
+    }
+
+    fn malformed_line_number(error: &Error) -> Option<usize> {
+        match RustGitError::from_io_error(error) {
+            Some(RustGitError::InvalidRef(RefError::MalformedLine { line_number, .. })) => {
+                Some(*line_number)
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn peeled_lines_belong_to_the_ref_before() {
+        let contents = format!(
+            "{TAG}\trefs/tags/v1.0\n{COMMIT}\trefs/tags/v1.0^{{}}\n{BRANCH}\trefs/heads/main\n"
+        );
+        let info_refs = InfoRefs::parse(&contents).unwrap();
+        assert_eq!(
+            info_refs.refs(),
+            [
+                InfoRef {
+                    name: "refs/tags/v1.0".to_string(),
+                    hash: hash(TAG),
+                    peeled: Some(hash(COMMIT)),
+                },
+                InfoRef {
+                    name: "refs/heads/main".to_string(),
+                    hash: hash(BRANCH),
+                    peeled: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn names_must_match_exactly() {
+        let contents = format!("{TAG}\trefs/tags/v1.0\n{COMMIT}\trefs/tags/v1.0^{{}}\n");
+        let info_refs = InfoRefs::parse(&contents).unwrap();
+        assert_eq!(info_refs.get("refs/tags/v1.0").unwrap().hash, hash(TAG));
+        for name in ["v1.0", "tags/v1.0", "refs/tags/v1", "refs/tags/v1.0^{}"] {
+            assert_eq!(info_refs.get(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn peeled_lines_without_their_ref_are_malformed() {
+        for contents in [
+            // Nothing before it
+            format!("{COMMIT}\trefs/tags/v1.0^{{}}\n"),
+            // A different ref before it
+            format!("{TAG}\trefs/tags/v1.0\n{COMMIT}\trefs/tags/v2.0^{{}}\n"),
+            // Peeled twice
+            format!("{TAG}\trefs/tags/v1.0\n{COMMIT}\trefs/tags/v1.0^{{}}\n{COMMIT}\trefs/tags/v1.0^{{}}\n"),
+        ] {
+            let error = InfoRefs::parse(&contents).unwrap_err();
+            assert_eq!(
+                malformed_line_number(&error),
+                Some(contents.lines().count()),
+                "{}",
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn lines_need_a_hash_and_a_name() {
+        for line in [
+            format!("{TAG} refs/heads/main"),
+            format!("{TAG}\t"),
+            "not a hash\trefs/heads/main".to_string(),
+        ] {
+            let error = InfoRefs::parse(&line).unwrap_err();
+            assert_eq!(malformed_line_number(&error), Some(1), "{}", line);
+        }
+    }
+
+    #[test]
+    fn missing_file_is_a_typed_error() {
+        let path = std::env::temp_dir().join("rustgit-no-such-info-refs");
+        let error = InfoRefs::read(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(matches!(
+            RustGitError::from_io_error(&error),
+            Some(RustGitError::InvalidRef(RefError::MissingFile(missing))) if *missing == path
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use super::RefError;
+use crate::hash::{hex_to_hash, Hash};
+
+// The optional first line of a packed-refs file, followed by its traits
+const HEADER_PREFIX: &str = "# pack-refs with:";
+// A line starting with this gives the peeled value of the ref before it
+const PEELED_PREFIX: char = '^';
+
+/// A ref stored in the `packed-refs` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub hash: Hash,
+    /// For an annotated tag, the object the tag chain ends at
+    pub peeled: Option<Hash>,
+}
+
+/// The contents of a `packed-refs` file.
+/// Git moves refs here from their own files (e.g. when running `git gc`)
+/// so repositories with many tags and branches don't need a file for each.
+#[derive(Debug, Clone, Default)]
+pub struct PackedRefs {
+    // Sorted by name
+    refs: Vec<PackedRef>,
+    // The "peeled" trait: every ref under `refs/tags/` that peels has a `^` line
+    peeled_tags: bool,
+    // The "fully-peeled" trait: every ref that peels has a `^` line
+    fully_peeled: bool,
+}
+
+fn malformed_line(line_number: usize, line: &str) -> Error {
+    RefError::MalformedLine {
+        file: "packed-refs",
+        line_number,
+        line: line.to_string(),
+    }
+    .into()
+}
+
+impl PackedRefs {
+    /// Reads a `packed-refs` file. A missing file means there are no packed refs.
+    pub fn read(path: &Path) -> io::Result<PackedRefs> {
+        match fs::read_to_string(path) {
+            Ok(contents) => PackedRefs::parse(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(PackedRefs::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parses the contents of a `packed-refs` file.
+    /// Each line is "<hash> <refname>",
+    /// optionally followed by a "^<hash>" line with the peeled value.
+    pub fn parse(contents: &str) -> io::Result<PackedRefs> {
+        let mut packed_refs = PackedRefs::default();
+        let mut sorted = false;
+        for (line_index, line) in contents.lines().enumerate() {
+            let line_number = line_index + 1;
+            if let Some(traits) = line.strip_prefix(HEADER_PREFIX) {
+                for packed_trait in traits.split_whitespace() {
+                    match packed_trait {
+                        "peeled" => packed_refs.peeled_tags = true,
+                        "fully-peeled" => packed_refs.fully_peeled = true,
+                        "sorted" => sorted = true,
+                        // Other traits don't change how the file is read
+                        _ => {}
+                    }
+                }
+            } else if let Some(peeled) = line.strip_prefix(PEELED_PREFIX) {
+                let peeled = hex_to_hash(peeled.as_bytes())
+                    .ok_or_else(|| malformed_line(line_number, line))?;
+                let packed_ref = packed_refs
+                    .refs
+                    .last_mut()
+                    .filter(|packed_ref| packed_ref.peeled.is_none())
+                    .ok_or_else(|| malformed_line(line_number, line))?;
+                packed_ref.peeled = Some(peeled);
+            } else if !line.starts_with('#') {
+                let (hash, name) = line
+                    .split_once(' ')
+                    .ok_or_else(|| malformed_line(line_number, line))?;
+                let hash = hex_to_hash(hash.as_bytes())
+                    .ok_or_else(|| malformed_line(line_number, line))?;
+                packed_refs.refs.push(PackedRef {
+                    name: name.to_string(),
+                    hash,
+                    peeled: None,
+                });
+            }
+        }
+        if !sorted {
+            packed_refs
+                .refs
+                .sort_by(|ref1, ref2| ref1.name.cmp(&ref2.name));
+        }
+        Ok(packed_refs)
+    }
+
+    /// All the packed refs, sorted by name
+    pub fn refs(&self) -> &[PackedRef] {
+        &self.refs
+    }
+
+    /// Finds a ref by its full name, e.g. `refs/heads/main`
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        let position = self
+            .refs
+            .binary_search_by(|packed_ref| packed_ref.name.as_str().cmp(name))
+            .ok()?;
+        Some(&self.refs[position])
+    }
+
+    /// Whether the file would have a `^` line for this ref if it peeled
+    /// to a different object. If so, a ref without one isn't an annotated tag.
+    pub fn is_peeled(&self, packed_ref: &PackedRef) -> bool {
+        packed_ref.peeled.is_some()
+            || self.fully_peeled
+            || (self.peeled_tags && packed_ref.name.starts_with("refs/tags/"))
+    }
+}
@@ -0,0 +1,472 @@
+use std::cell::{OnceCell, RefCell};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Error};
+use std::path::{Path, PathBuf};
+
+use crate::commit_graph::CommitGraph;
+use crate::config::Config;
+use crate::error::RustGitError;
+use crate::hash::ObjectFormat;
+use crate::pack::packfile::PackSlot;
+use crate::pack::{DeltaBaseCache, MultiPackIndex};
+
+const GIT_DIR_ENVIRONMENT: &str = "GIT_DIR";
+const GIT_WORK_TREE_ENVIRONMENT: &str = "GIT_WORK_TREE";
+const GIT_OBJECT_DIRECTORY_ENVIRONMENT: &str = "GIT_OBJECT_DIRECTORY";
+const GIT_CEILING_DIRECTORIES_ENVIRONMENT: &str = "GIT_CEILING_DIRECTORIES";
+
+const DELTA_BASE_CACHE_LIMIT_CONFIG: &str = "core.deltaBaseCacheLimit";
+// The hash function the repository uses, "sha1" if unset
+const OBJECT_FORMAT_CONFIG: &str = "extensions.objectFormat";
+
+const DOT_GIT: &str = ".git";
+// Refs under these prefixes belong to each worktree, like HEAD
+const PER_WORKTREE_REF_PREFIXES: [&str; 3] = ["refs/bisect/", "refs/worktree/", "refs/rewritten/"];
+const GITDIR_PREFIX: &str = "gitdir:";
+
+/// The locations of a repository's files.
+/// All paths are absolute.
+#[derive(Debug)]
+pub struct Repository {
+    // The directory holding HEAD, e.g. `project/.git`
+    git_dir: PathBuf,
+    // The directory holding refs and config. This is the same as `git_dir`
+    // except in a linked worktree, where it is the main repository's `.git`.
+    common_dir: PathBuf,
+    objects_dir: PathBuf,
+    // Bare repositories have no work tree
+    work_tree: Option<PathBuf>,
+    config: Config,
+    object_format: ObjectFormat,
+    pub(crate) packs: OnceCell<Vec<PackSlot>>,
+    pub(crate) multi_pack_index: OnceCell<io::Result<Option<MultiPackIndex>>>,
+    pub(crate) commit_graph: OnceCell<io::Result<Option<CommitGraph>>>,
+    pub(crate) delta_base_cache: RefCell<DeltaBaseCache>,
+}
+
+// The environment variables that change where a repository's files are found.
+// They are read once, so tests can pass their own without touching the process's.
+#[derive(Debug, Default)]
+struct Environment {
+    git_dir: Option<OsString>,
+    work_tree: Option<OsString>,
+    object_directory: Option<OsString>,
+    ceiling_directories: Option<OsString>,
+}
+
+impl Environment {
+    fn read() -> Environment {
+        Environment {
+            git_dir: env::var_os(GIT_DIR_ENVIRONMENT),
+            work_tree: env::var_os(GIT_WORK_TREE_ENVIRONMENT),
+            object_directory: env::var_os(GIT_OBJECT_DIRECTORY_ENVIRONMENT),
+            ceiling_directories: env::var_os(GIT_CEILING_DIRECTORIES_ENVIRONMENT),
+        }
+    }
+}
+
+// Checks whether a directory looks like a git directory,
+// the same way git does: it must have HEAD, objects and refs
+fn is_git_directory(path: &Path) -> bool {
+    let common_dir = read_common_dir(path).unwrap_or_else(|| path.to_path_buf());
+    path.join("HEAD").is_file()
+        && common_dir.join("objects").is_dir()
+        && common_dir.join("refs").is_dir()
+}
+
+// A linked worktree's git directory names the main one in its `commondir` file
+fn read_common_dir(git_dir: &Path) -> Option<PathBuf> {
+    let common_dir = fs::read_to_string(git_dir.join("commondir")).ok()?;
+    Some(git_dir.join(common_dir.trim_end()))
+}
+
+// Reads a `.git` file containing `gitdir: <path>`, as used by
+// linked worktrees and submodules. Relative paths are relative to the file.
+fn read_gitdir_file(file: &Path) -> io::Result<PathBuf> {
+    let contents = fs::read_to_string(file)?;
+    let git_dir = contents
+        .trim_end()
+        .strip_prefix(GITDIR_PREFIX)
+        .ok_or_else(|| RustGitError::InvalidGitFile(file.to_path_buf()))?
+        .trim();
+    let directory = file.parent().unwrap_or(Path::new(""));
+    Ok(directory.join(git_dir))
+}
+
+// Makes a path from the environment absolute, relative to the current directory
+fn absolute_path(path: impl Into<PathBuf>) -> io::Result<PathBuf> {
+    let path = path.into();
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+// Resolves `.` and `..` components and symlinks where the path exists
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn not_a_repository(path: PathBuf, searched_parents: bool) -> Error {
+    RustGitError::NotARepository {
+        path,
+        searched_parents,
+    }
+    .into()
+}
+
+fn ceiling_directories(environment: &Environment) -> Vec<PathBuf> {
+    let ceilings = environment.ceiling_directories.clone().unwrap_or_default();
+    env::split_paths(&ceilings)
+        // Only absolute entries count; empty and relative ones are ignored
+        .filter(|ceiling| ceiling.is_absolute())
+        .map(|ceiling| normalize(&ceiling))
+        .collect()
+}
+
+impl Repository {
+    /// Finds the repository containing `path`, like git does.
+    ///
+    /// If `GIT_DIR` is set it names the git directory and no search happens.
+    /// Otherwise each directory from `path` upwards is checked for a `.git`
+    /// directory, a `.git` file pointing elsewhere, or a bare repository,
+    /// stopping before any directory listed in `GIT_CEILING_DIRECTORIES`.
+    /// `GIT_WORK_TREE` and `GIT_OBJECT_DIRECTORY` override the
+    /// work tree and objects directory that would otherwise be used.
+    pub fn discover<P: AsRef<Path>>(path: P) -> io::Result<Repository> {
+        Repository::discover_with(path.as_ref(), &Environment::read())
+    }
+
+    fn discover_with(path: &Path, environment: &Environment) -> io::Result<Repository> {
+        let work_tree_override = environment
+            .work_tree
+            .clone()
+            .map(absolute_path)
+            .transpose()?;
+
+        if let Some(git_dir) = environment.git_dir.clone() {
+            let git_dir = absolute_path(git_dir)?;
+            if !is_git_directory(&git_dir) {
+                return Err(not_a_repository(git_dir, false));
+            }
+            // With GIT_DIR set, git treats the current directory as the work tree
+            let work_tree = match work_tree_override {
+                Some(work_tree) => Some(work_tree),
+                None => Some(env::current_dir()?),
+            };
+            return Repository::open_with(&git_dir, work_tree, environment);
+        }
+
+        let start = normalize(&absolute_path(path)?);
+        let ceilings = ceiling_directories(environment);
+        let mut directory = start.as_path();
+        loop {
+            let dot_git = directory.join(DOT_GIT);
+            if dot_git.is_dir() && is_git_directory(&dot_git) {
+                let work_tree = work_tree_override.unwrap_or_else(|| directory.to_path_buf());
+                return Repository::open_with(&dot_git, Some(work_tree), environment);
+            }
+            if dot_git.is_file() {
+                let git_dir = read_gitdir_file(&dot_git)?;
+                if !is_git_directory(&git_dir) {
+                    return Err(not_a_repository(git_dir, false));
+                }
+                let work_tree = work_tree_override.unwrap_or_else(|| directory.to_path_buf());
+                return Repository::open_with(&git_dir, Some(work_tree), environment);
+            }
+            // The directory may itself be a bare repository
+            if is_git_directory(directory) {
+                return Repository::open_with(directory, work_tree_override, environment);
+            }
+
+            match directory.parent() {
+                Some(parent) if !ceilings.iter().any(|ceiling| ceiling == parent) => {
+                    directory = parent;
+                }
+                _ => break,
+            }
+        }
+        Err(not_a_repository(start, true))
+    }
+
+    /// Opens the repository with the given git directory.
+    /// The work tree is dropped if the repository is configured as bare.
+    pub fn open(git_dir: &Path, work_tree: Option<PathBuf>) -> io::Result<Repository> {
+        Repository::open_with(git_dir, work_tree, &Environment::read())
+    }
+
+    fn open_with(
+        git_dir: &Path,
+        work_tree: Option<PathBuf>,
+        environment: &Environment,
+    ) -> io::Result<Repository> {
+        let git_dir = normalize(git_dir);
+        let common_dir = read_common_dir(&git_dir)
+            .map(|common_dir| normalize(&common_dir))
+            .unwrap_or_else(|| git_dir.clone());
+        let config = Config::read(&common_dir.join("config"))?;
+        let objects_dir = match environment.object_directory.clone() {
+            Some(objects_dir) => absolute_path(objects_dir)?,
+            None => common_dir.join("objects"),
+        };
+        let work_tree = match config.get_bool("core.bare") {
+            Some(true) if environment.work_tree.is_none() => None,
+            _ => work_tree,
+        };
+        let object_format = match config.get(OBJECT_FORMAT_CONFIG) {
+            Some(name) => ObjectFormat::from_name(name)
+                .ok_or_else(|| RustGitError::UnknownObjectFormat(name.to_string()))?,
+            None => ObjectFormat::Sha1,
+        };
+        let delta_base_cache = match config.get_size(DELTA_BASE_CACHE_LIMIT_CONFIG) {
+            Some(limit) => DeltaBaseCache::new(limit),
+            None => DeltaBaseCache::default(),
+        };
+        Ok(Repository {
+            git_dir,
+            common_dir,
+            objects_dir,
+            work_tree,
+            config,
+            object_format,
+            packs: OnceCell::new(),
+            multi_pack_index: OnceCell::new(),
+            commit_graph: OnceCell::new(),
+            delta_base_cache: RefCell::new(delta_base_cache),
+        })
+    }
+
+    pub fn git_dir(&self) -> &Path {
+        &self.git_dir
+    }
+
+    pub fn common_dir(&self) -> &Path {
+        &self.common_dir
+    }
+
+    pub fn work_tree(&self) -> Option<&Path> {
+        self.work_tree.as_deref()
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.work_tree.is_none()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The hash function objects are named with, from `extensions.objectFormat`
+    pub fn object_format(&self) -> ObjectFormat {
+        self.object_format
+    }
+
+    /// Sets how many bytes of delta bases may be cached while reading packs,
+    /// overriding `core.deltaBaseCacheLimit`
+    pub fn set_delta_base_cache_limit(&self, limit: usize) {
+        self.delta_base_cache.borrow_mut().set_limit(limit);
+    }
+
+    /// The directory holding loose objects and the `pack` directory
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    pub fn packs_dir(&self) -> PathBuf {
+        self.objects_dir.join("pack")
+    }
+
+    /// HEAD belongs to each worktree, unlike the rest of the refs
+    pub fn head_file(&self) -> PathBuf {
+        self.git_dir.join("HEAD")
+    }
+
+    pub fn branch_refs_dir(&self) -> PathBuf {
+        self.common_dir.join("refs").join("heads")
+    }
+
+    /// The file for a ref, given its full name, e.g. `refs/heads/main`.
+    /// Refs outside `refs/`, like HEAD, and a few namespaces inside it
+    /// belong to each worktree; the rest are shared.
+    pub fn ref_file(&self, name: &str) -> PathBuf {
+        let per_worktree = !name.starts_with("refs/")
+            || PER_WORKTREE_REF_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if per_worktree {
+            self.git_dir.join(name)
+        } else {
+            self.common_dir.join(name)
+        }
+    }
+
+    /// Refs moved out of their own files by `git pack-refs`
+    pub fn packed_refs_file(&self) -> PathBuf {
+        self.common_dir.join("packed-refs")
+    }
+
+    /// The refs list written by `git update-server-info`
+    pub fn info_refs_file(&self) -> PathBuf {
+        self.common_dir.join("info").join("refs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn unknown_object_format_is_unsupported() {
+        let test_repo = TestRepo::new();
+        test_repo.write("config", "[extensions]\n\tobjectFormat = md5\n");
+        let error = Repository::open(&test_repo.git_dir(), None).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::UnknownObjectFormat("md5".to_string()))
+        );
+    }
+
+    #[test]
+    fn gitfile_must_name_a_git_directory() {
+        let test_repo = TestRepo::new();
+        let dot_git = test_repo.git_dir().join("worktree.git");
+        fs::write(&dot_git, "not a gitfile\n").unwrap();
+        let error = read_gitdir_file(&dot_git).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::InvalidGitFile(dot_git))
+        );
+    }
+
+    // A directory under the work tree, e.g. "src/lib"
+    fn make_dir(test_repo: &TestRepo, path: &str) -> PathBuf {
+        let directory = test_repo.git_dir().parent().unwrap().join(path);
+        fs::create_dir_all(&directory).unwrap();
+        normalize(&directory)
+    }
+
+    #[test]
+    fn discovery_walks_up_from_a_subdirectory() {
+        let test_repo = TestRepo::new();
+        let subdirectory = make_dir(&test_repo, "src/lib");
+        let repository = Repository::discover_with(&subdirectory, &Environment::default()).unwrap();
+        assert_eq!(repository.git_dir(), normalize(&test_repo.git_dir()));
+        assert_eq!(repository.common_dir(), repository.git_dir());
+        assert_eq!(
+            repository.work_tree(),
+            Some(subdirectory.parent().unwrap().parent().unwrap())
+        );
+        assert_eq!(
+            repository.objects_dir(),
+            repository.git_dir().join("objects")
+        );
+    }
+
+    #[test]
+    fn git_dir_environment_skips_the_search() {
+        let test_repo = TestRepo::new();
+        let elsewhere = make_dir(&test_repo, "elsewhere");
+        let mut environment = Environment {
+            git_dir: Some(test_repo.git_dir().into()),
+            ..Environment::default()
+        };
+        let repository = Repository::discover_with(&elsewhere, &environment).unwrap();
+        assert_eq!(repository.git_dir(), normalize(&test_repo.git_dir()));
+        // The current directory is the work tree, wherever the search would have started
+        assert_eq!(
+            repository.work_tree(),
+            Some(env::current_dir().unwrap().as_path())
+        );
+
+        environment.work_tree = Some(elsewhere.clone().into());
+        let repository = Repository::discover_with(Path::new("/"), &environment).unwrap();
+        assert_eq!(repository.work_tree(), Some(elsewhere.as_path()));
+
+        // GIT_DIR must name a git directory itself, not a directory containing one
+        let work_tree = test_repo.git_dir().parent().unwrap().to_path_buf();
+        environment.git_dir = Some(work_tree.clone().into());
+        let error = Repository::discover_with(&work_tree, &environment).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::NotARepository {
+                path: work_tree,
+                searched_parents: false,
+            })
+        );
+    }
+
+    #[test]
+    fn ceiling_directories_stop_the_search() {
+        let test_repo = TestRepo::new();
+        let subdirectory = make_dir(&test_repo, "src/lib");
+        let work_tree = subdirectory.parent().unwrap().parent().unwrap();
+        let ceilings = |ceilings: &[&Path]| Environment {
+            ceiling_directories: Some(env::join_paths(ceilings).unwrap()),
+            ..Environment::default()
+        };
+
+        // The ceiling itself is never checked, so the work tree isn't found
+        let error = Repository::discover_with(&subdirectory, &ceilings(&[work_tree])).unwrap_err();
+        assert_eq!(
+            RustGitError::from_io_error(&error),
+            Some(&RustGitError::NotARepository {
+                path: subdirectory.clone(),
+                searched_parents: true,
+            })
+        );
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+
+        // Ceilings above the repository don't get in the way,
+        // and relative ones are ignored
+        let environment = ceilings(&[work_tree.parent().unwrap(), Path::new("src")]);
+        let repository = Repository::discover_with(&subdirectory, &environment).unwrap();
+        assert_eq!(repository.work_tree(), Some(work_tree));
+    }
+
+    #[test]
+    fn object_directory_environment_moves_the_objects() {
+        let test_repo = TestRepo::new();
+        let objects_dir = make_dir(&test_repo, "objects");
+        let environment = Environment {
+            object_directory: Some(objects_dir.clone().into()),
+            ..Environment::default()
+        };
+        let repository = Repository::discover_with(&objects_dir, &environment).unwrap();
+        assert_eq!(repository.git_dir(), normalize(&test_repo.git_dir()));
+        assert_eq!(repository.objects_dir(), objects_dir);
+        assert_eq!(repository.packs_dir(), objects_dir.join("pack"));
+    }
+
+    #[test]
+    fn bare_repositories_have_no_work_tree() {
+        // A git directory found directly, rather than as `.git`
+        let test_repo = TestRepo::new();
+        let git_dir = normalize(&test_repo.git_dir());
+        let repository =
+            Repository::discover_with(&git_dir.join("refs/heads"), &Environment::default())
+                .unwrap();
+        assert_eq!(repository.git_dir(), git_dir);
+        assert!(repository.is_bare());
+
+        // A `.git` directory configured as bare
+        test_repo.write("config", "[core]\n\tbare = true\n");
+        let work_tree = git_dir.parent().unwrap();
+        let repository = Repository::discover_with(work_tree, &Environment::default()).unwrap();
+        assert_eq!(repository.git_dir(), git_dir);
+        assert!(repository.is_bare());
+
+        // unless a work tree is given anyway
+        let environment = Environment {
+            work_tree: Some(work_tree.into()),
+            ..Environment::default()
+        };
+        let repository = Repository::discover_with(work_tree, &environment).unwrap();
+        assert_eq!(repository.work_tree(), Some(work_tree));
+    }
+}
@@ -0,0 +1,709 @@
+// Revision expressions, the arguments `git rev-parse` understands:
+// a hash or ref name, followed by any number of suffixes that move
+// to ancestors (`~n`, `^n`) or peel to another type (`^{tree}`, `^{}`),
+// as well as `rev:path` for a file in a commit and `:/regex` to search messages
+
+use regex::Regex;
+use std::collections::{BinaryHeap, HashSet};
+use std::io::{self, Error, ErrorKind};
+
+use crate::error::RustGitError;
+use crate::hash::{hex_to_hash, Hash, HashPrefix};
+use crate::object::ObjectType;
+use crate::refs::ResolvedRef;
+use crate::repository::Repository;
+
+// Where a short ref name is looked for, in order.
+// `refs/remotes/<name>/HEAD` is also tried, to find a remote's default branch.
+const DWIM_REF_FORMATS: [&str; 5] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+];
+const REMOTE_HEAD_FORMAT: &str = "refs/remotes/{}/HEAD";
+
+fn unknown_revision(revision: &str) -> Error {
+    RustGitError::UnknownRevision(revision.to_string()).into()
+}
+
+fn invalid_revision(revision: &str, reason: &str) -> Error {
+    RustGitError::InvalidRevision {
+        revision: revision.to_string(),
+        reason: reason.to_string(),
+    }
+    .into()
+}
+
+// Finds the first `:` that isn't inside braces, e.g. not the one in `@{...}`
+fn find_path_separator(revision: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (index, c) in revision.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+// Splits the decimal number off the start of a suffix, e.g. "2^{tree}" -> (Some(2), "^{tree}")
+fn take_number(suffix: &str) -> (Option<usize>, &str) {
+    let digits = suffix
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(suffix.len());
+    let (number, rest) = suffix.split_at(digits);
+    (number.parse().ok(), rest)
+}
+
+// Maps a ref on a remote to where a fetch refspec stores it locally,
+// or returns None if the refspec doesn't cover the ref
+fn map_refspec(refspec: &str, remote_ref: &str) -> Option<String> {
+    let refspec = refspec.strip_prefix('+').unwrap_or(refspec);
+    let (source, destination) = refspec.split_once(':')?;
+    match (source.split_once('*'), destination.split_once('*')) {
+        (Some((source_prefix, source_suffix)), Some((destination_prefix, destination_suffix))) => {
+            let matched = remote_ref
+                .strip_prefix(source_prefix)?
+                .strip_suffix(source_suffix)?;
+            Some(format!(
+                "{}{}{}",
+                destination_prefix, matched, destination_suffix
+            ))
+        }
+        (None, None) if source == remote_ref => Some(destination.to_string()),
+        _ => None,
+    }
+}
+
+impl Repository {
+    /// Finds the object named by a revision expression, like `git rev-parse`.
+    /// Supported forms are:
+    /// - a full hash, or a ref name looked up as given, then under
+    ///   `refs/`, `refs/tags/`, `refs/heads/` and `refs/remotes/`,
+    ///   or else an abbreviated hash
+    /// - `@` for HEAD
+    /// - `<branch>@{upstream}` (or `@{u}`) for the branch it tracks;
+    ///   with no branch, the current one
+    /// - `<rev>~<n>` for the nth first-parent ancestor
+    /// - `<rev>^<n>` for the nth parent (`^0` is the commit itself)
+    /// - `<rev>^{<type>}` to peel tags (and commits, for `tree`) down to a type,
+    ///   and `<rev>^{}` to peel tags down to whatever they point to
+    /// - `<rev>:<path>` for the tree or blob at a path in a commit or tree
+    /// - `:/<regex>` for the newest commit whose message matches,
+    ///   reachable from any ref
+    pub fn rev_parse(&self, revision: &str) -> io::Result<Hash> {
+        if let Some(pattern) = revision.strip_prefix(":/") {
+            return self.find_commit_by_message(pattern);
+        }
+        if let Some(separator) = find_path_separator(revision) {
+            let (tree_revision, path) = (&revision[..separator], &revision[separator + 1..]);
+            if tree_revision.is_empty() {
+                return Err(invalid_revision(revision, "the index is not supported"));
+            }
+            let tree = self.peel_to_type(self.rev_parse(tree_revision)?, ObjectType::Tree)?;
+            return self.find_path(tree, path);
+        }
+
+        // The name ends where the first suffix starts
+        let name_end = revision
+            .find(['~', '^'])
+            .into_iter()
+            .chain(revision.find("@{"))
+            .min()
+            .unwrap_or(revision.len());
+        let (name, mut suffixes) = revision.split_at(name_end);
+
+        let mut hash = match suffixes.strip_prefix("@{") {
+            Some(reflog_suffix) => {
+                let (selector, rest) = reflog_suffix
+                    .split_once('}')
+                    .ok_or_else(|| invalid_revision(revision, "missing }"))?;
+                suffixes = rest;
+                match selector.to_ascii_lowercase().as_str() {
+                    "upstream" | "u" => {
+                        let upstream = self.upstream(name)?;
+                        self.resolve_hash(&upstream)?
+                            .ok_or_else(|| unknown_revision(&upstream))?
+                    }
+                    _ => return Err(invalid_revision(revision, "reflogs are not supported")),
+                }
+            }
+            None => {
+                let name = if name == "@" { "HEAD" } else { name };
+                self.resolve_name(name)?
+                    .ok_or_else(|| unknown_revision(revision))?
+            }
+        };
+
+        while !suffixes.is_empty() {
+            if let Some(rest) = suffixes.strip_prefix('~') {
+                let (generations, rest) = take_number(rest);
+                for _ in 0..generations.unwrap_or(1) {
+                    hash = self.nth_parent(hash, 1, revision)?;
+                }
+                suffixes = rest;
+            } else if let Some(rest) = suffixes.strip_prefix("^{") {
+                let (type_name, rest) = rest
+                    .split_once('}')
+                    .ok_or_else(|| invalid_revision(revision, "missing }"))?;
+                hash = match type_name {
+                    "" => self.peel(hash)?,
+                    // Only checks that the object exists
+                    "object" => self.read_object_contents(hash).map(|_| hash)?,
+                    _ => {
+                        let object_type = ObjectType::from_name(type_name).ok_or_else(|| {
+                            invalid_revision(revision, &format!("unknown type {}", type_name))
+                        })?;
+                        self.peel_to_type(hash, object_type)?
+                    }
+                };
+                suffixes = rest;
+            } else if let Some(rest) = suffixes.strip_prefix('^') {
+                let (parent, rest) = take_number(rest);
+                hash = match parent.unwrap_or(1) {
+                    0 => self.peel_to_type(hash, ObjectType::Commit)?,
+                    parent => self.nth_parent(hash, parent, revision)?,
+                };
+                suffixes = rest;
+            } else {
+                return Err(invalid_revision(revision, "unknown suffix"));
+            }
+        }
+        Ok(hash)
+    }
+
+    // Looks up a full hash, then a ref name, then an abbreviated hash
+    fn resolve_name(&self, name: &str) -> io::Result<Option<Hash>> {
+        if name.len() == self.object_format().hex_len() {
+            if let Some(hash) = hex_to_hash(name.as_bytes()) {
+                return Ok(Some(hash));
+            }
+        }
+        if let Some((_, hash)) = self.dwim_ref(name)? {
+            return Ok(Some(hash));
+        }
+        match HashPrefix::parse(name) {
+            Some(prefix) => match self.resolve_abbreviated_hash(&prefix) {
+                Ok(hash) => Ok(Some(hash)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Resolves a ref, treating a missing ref (or unborn branch) as None
+    fn resolve_hash(&self, name: &str) -> io::Result<Option<Hash>> {
+        match self.resolve_ref(name) {
+            Ok(ResolvedRef::Hash { hash, .. }) => Ok(Some(hash)),
+            Ok(ResolvedRef::Unborn { .. }) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Expands a short ref name the way git does ("do what I mean"),
+    /// trying each place a ref can live in order.
+    /// Returns the full name of the first ref found and its hash.
+    pub fn dwim_ref(&self, name: &str) -> io::Result<Option<(String, Hash)>> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+        for format in DWIM_REF_FORMATS.iter().chain([&REMOTE_HEAD_FORMAT]) {
+            let full_name = format.replace("{}", name);
+            // Only HEAD and the like are looked up outside `refs/`
+            let outside_refs = !full_name.starts_with("refs/");
+            if outside_refs && name.chars().any(|c| !c.is_ascii_uppercase() && c != '_') {
+                continue;
+            }
+            if let Some(hash) = self.resolve_hash(&full_name)? {
+                return Ok(Some((full_name, hash)));
+            }
+        }
+        Ok(None)
+    }
+
+    // The full name of the ref a branch tracks, from its
+    // `branch.<name>.remote` and `branch.<name>.merge` settings.
+    // An empty branch name means the current branch.
+    fn upstream(&self, branch: &str) -> io::Result<String> {
+        let branch = match branch {
+            "" | "@" | "HEAD" => match self.resolve_ref("HEAD")? {
+                ResolvedRef::Hash { name, .. } | ResolvedRef::Unborn { name } => name,
+            },
+            branch => branch.to_string(),
+        };
+        let branch = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+        let no_upstream = || RustGitError::NoUpstream(branch.to_string());
+
+        let remote = self
+            .config()
+            .get(&format!("branch.{}.remote", branch))
+            .ok_or_else(no_upstream)?;
+        let merge = self
+            .config()
+            .get(&format!("branch.{}.merge", branch))
+            .ok_or_else(no_upstream)?;
+        // A remote of "." means the upstream is a local branch
+        if remote == "." {
+            return Ok(merge.to_string());
+        }
+        // Otherwise, the remote's fetch refspecs say where its branch is copied,
+        // e.g. "+refs/heads/*:refs/remotes/origin/*"
+        self.config()
+            .get_all(&format!("remote.{}.fetch", remote))
+            .into_iter()
+            .find_map(|refspec| map_refspec(refspec, merge))
+            .ok_or_else(|| RustGitError::UpstreamNotTracked(merge.to_string()).into())
+    }
+
+    // Finds a commit's nth parent, starting from 1.
+    // Tags pointing to commits are peeled first.
+    fn nth_parent(&self, hash: Hash, parent: usize, revision: &str) -> io::Result<Hash> {
+        let commit = self.peel_to_type(hash, ObjectType::Commit)?;
+        let parents = self.read_graph_commit(commit)?.parents;
+        parents
+            .get(parent - 1)
+            .copied()
+            .ok_or_else(|| unknown_revision(revision))
+    }
+
+    /// Follows a chain of tags to the first object that isn't a tag, like `<rev>^{}`.
+    /// Any other object is returned as it is.
+    pub fn peel(&self, mut hash: Hash) -> io::Result<Hash> {
+        // Only tags are read; the type of anything else is in its header
+        while self.read_object_header(hash)?.0 == ObjectType::Tag {
+            hash = self.read_tag(hash)?.object;
+        }
+        Ok(hash)
+    }
+
+    /// Follows tags, and commits to their trees, until reaching
+    /// an object of the given type, like `<rev>^{<type>}`
+    pub fn peel_to_type(&self, mut hash: Hash, object_type: ObjectType) -> io::Result<Hash> {
+        loop {
+            let (found_type, _) = self.read_object_header(hash)?;
+            hash = match found_type {
+                _ if found_type == object_type => return Ok(hash),
+                ObjectType::Tag => self.read_tag(hash)?.object,
+                ObjectType::Commit if object_type == ObjectType::Tree => {
+                    self.read_graph_commit(hash)?.tree
+                }
+                _ => {
+                    return Err(RustGitError::UnexpectedType {
+                        hash,
+                        expected: object_type,
+                        found: found_type,
+                    }
+                    .into())
+                }
+            };
+        }
+    }
+
+    // Searches history from every ref, newest commits first,
+    // for a commit whose message matches a regex.
+    // A pattern starting with "!-" finds a message that doesn't match,
+    // and "!!" stands for a literal "!".
+    fn find_commit_by_message(&self, pattern: &str) -> io::Result<Hash> {
+        let (pattern, negated) = match pattern.strip_prefix('!') {
+            Some(rest) if rest.starts_with('!') => (rest, false),
+            Some(rest) => match rest.strip_prefix('-') {
+                Some(rest) => (rest, true),
+                None => return Err(invalid_revision(pattern, "unknown :/! modifier")),
+            },
+            None => (pattern, false),
+        };
+        let regex = Regex::new(pattern).map_err(|e| invalid_revision(pattern, &e.to_string()))?;
+
+        let mut starts: Vec<Hash> = self
+            .list_refs()?
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .collect();
+        starts.extend(self.resolve_hash("HEAD")?);
+
+        // Visit commits newest first, using a heap ordered by commit time
+        let mut queue = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        for hash in starts {
+            let Ok(hash) = self.peel_to_type(hash, ObjectType::Commit) else {
+                continue;
+            };
+            if seen.insert(hash) {
+                queue.push((self.read_graph_commit(hash)?.commit_time, hash));
+            }
+        }
+        while let Some((_, hash)) = queue.pop() {
+            let commit = self.read_commit(hash)?;
+            if regex.is_match(&commit.message_text()) != negated {
+                return Ok(hash);
+            }
+            for parent in commit.parents {
+                if seen.insert(parent) {
+                    queue.push((self.read_graph_commit(parent)?.commit_time, parent));
+                }
+            }
+        }
+        Err(unknown_revision(&format!(":/{}", pattern)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    // The objects written by `write_history()`
+    struct History {
+        file: Hash,
+        nested: Hash,
+        dir: Hash,
+        root_tree: Hash,
+        // main: first -- second -- merge, with side branching from first
+        first: Hash,
+        second: Hash,
+        side: Hash,
+        merge: Hash,
+        // An annotated tag of the merge
+        tag: Hash,
+    }
+
+    fn tree(entries: &[(&str, &str, Hash)]) -> Vec<u8> {
+        let mut tree = vec![];
+        for (mode, name, hash) in entries {
+            tree.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+            tree.extend_from_slice(hash.as_bytes());
+        }
+        tree
+    }
+
+    fn commit(
+        test_repo: &TestRepo,
+        tree: Hash,
+        parents: &[Hash],
+        time: u64,
+        message: &str,
+    ) -> Hash {
+        let mut commit = format!("tree {}\n", tree);
+        for parent in parents {
+            commit += &format!("parent {}\n", parent);
+        }
+        commit += &format!(
+            "author A U Thor <author@example.com> {time} +0000\n\
+             committer C O Mitter <committer@example.com> {time} +0000\n\n{message}\n"
+        );
+        test_repo.write_object(ObjectType::Commit, commit.as_bytes())
+    }
+
+    fn write_history(test_repo: &TestRepo) -> History {
+        let file = test_repo.write_object(ObjectType::Blob, b"file\n");
+        let nested = test_repo.write_object(ObjectType::Blob, b"nested\n");
+        let dir = test_repo.write_object(ObjectType::Tree, &tree(&[("100644", "nested", nested)]));
+        let root_tree = test_repo.write_object(
+            ObjectType::Tree,
+            &tree(&[("40000", "dir", dir), ("100644", "file", file)]),
+        );
+        let first = commit(test_repo, root_tree, &[], 1700000000, "first");
+        let second = commit(test_repo, root_tree, &[first], 1700000100, "second");
+        let side = commit(test_repo, root_tree, &[first], 1700000200, "side branch");
+        let merge = commit(test_repo, root_tree, &[second, side], 1700000300, "merge");
+        let tag = test_repo.write_object(
+            ObjectType::Tag,
+            format!(
+                "object {merge}\ntype commit\ntag v1\n\
+                 tagger C O Mitter <committer@example.com> 1700000400 +0000\n\nv1\n"
+            )
+            .as_bytes(),
+        );
+        test_repo.write("refs/heads/main", format!("{merge}\n"));
+        test_repo.write("refs/heads/side", format!("{side}\n"));
+        test_repo.write("refs/tags/v1", format!("{tag}\n"));
+        History {
+            file,
+            nested,
+            dir,
+            root_tree,
+            first,
+            second,
+            side,
+            merge,
+            tag,
+        }
+    }
+
+    fn rev_parse_error(repository: &Repository, revision: &str) -> RustGitError {
+        let error = repository.rev_parse(revision).unwrap_err();
+        RustGitError::from_io_error(&error)
+            .unwrap_or_else(|| panic!("{}: {:?}", revision, error))
+            .clone()
+    }
+
+    #[test]
+    fn ancestors() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        let repository = test_repo.open();
+        for (revision, hash) in [
+            ("main", history.merge),
+            ("main~", history.second),
+            ("main~1", history.second),
+            ("main~2", history.first),
+            ("main^", history.second),
+            ("main^1", history.second),
+            ("main^2", history.side),
+            ("main^2~1", history.first),
+            ("main^^", history.first),
+            ("main^0", history.merge),
+            // Tags are peeled to the commit first
+            ("v1^0", history.merge),
+            ("v1~1", history.second),
+        ] {
+            assert_eq!(
+                repository.rev_parse(revision).unwrap(),
+                hash,
+                "{}",
+                revision
+            );
+        }
+    }
+
+    #[test]
+    fn peeling() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        let repository = test_repo.open();
+        for (revision, hash) in [
+            ("v1", history.tag),
+            ("v1^{}", history.merge),
+            ("v1^{tag}", history.tag),
+            ("v1^{object}", history.tag),
+            ("v1^{commit}", history.merge),
+            ("v1^{tree}", history.root_tree),
+            ("main^{}", history.merge),
+            ("main^{tree}", history.root_tree),
+        ] {
+            assert_eq!(
+                repository.rev_parse(revision).unwrap(),
+                hash,
+                "{}",
+                revision
+            );
+        }
+
+        assert_eq!(
+            rev_parse_error(&repository, "main^{blob}"),
+            RustGitError::UnexpectedType {
+                hash: history.merge,
+                expected: ObjectType::Blob,
+                found: ObjectType::Commit,
+            }
+        );
+        assert!(matches!(
+            rev_parse_error(&repository, "main^{nothing}"),
+            RustGitError::InvalidRevision { .. }
+        ));
+        assert!(matches!(
+            rev_parse_error(&repository, "main^{tree"),
+            RustGitError::InvalidRevision { .. }
+        ));
+    }
+
+    #[test]
+    fn head_and_upstream() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        test_repo.write("refs/remotes/origin/main", format!("{}\n", history.second));
+        test_repo.write(
+            "config",
+            "[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n\
+             [branch \"side\"]\n\tremote = .\n\tmerge = refs/heads/main\n\
+             [remote \"origin\"]\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+        );
+        let repository = test_repo.open();
+        for (revision, hash) in [
+            ("@", history.merge),
+            ("HEAD", history.merge),
+            ("@^2", history.side),
+            ("@{upstream}", history.second),
+            ("@{u}", history.second),
+            ("main@{u}", history.second),
+            ("main@{U}~1", history.first),
+            // A remote of "." tracks a local branch
+            ("side@{upstream}", history.merge),
+        ] {
+            assert_eq!(
+                repository.rev_parse(revision).unwrap(),
+                hash,
+                "{}",
+                revision
+            );
+        }
+
+        test_repo.write("HEAD", "ref: refs/heads/other\n");
+        let repository = test_repo.open();
+        assert_eq!(
+            rev_parse_error(&repository, "@{upstream}"),
+            RustGitError::NoUpstream("other".to_string())
+        );
+        assert!(matches!(
+            rev_parse_error(&repository, "main@{1}"),
+            RustGitError::InvalidRevision { .. }
+        ));
+    }
+
+    #[test]
+    fn paths() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        let repository = test_repo.open();
+        for (revision, hash) in [
+            ("main:file", history.file),
+            ("main:dir", history.dir),
+            ("main:dir/nested", history.nested),
+            ("main:", history.root_tree),
+            ("v1:dir/nested", history.nested),
+            ("main^{tree}:file", history.file),
+            ("main~2:dir/nested", history.nested),
+        ] {
+            assert_eq!(
+                repository.rev_parse(revision).unwrap(),
+                hash,
+                "{}",
+                revision
+            );
+        }
+
+        assert_eq!(
+            rev_parse_error(&repository, "main:dir/missing"),
+            RustGitError::EntryNotFound {
+                tree: history.dir,
+                name: "missing".to_string(),
+            }
+        );
+        assert!(matches!(
+            rev_parse_error(&repository, ":file"),
+            RustGitError::InvalidRevision { .. }
+        ));
+    }
+
+    #[test]
+    fn messages() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        let repository = test_repo.open();
+        for (revision, hash) in [
+            (":/second", history.second),
+            (":/^fir", history.first),
+            // The newest match wins
+            (":/^s", history.side),
+            (":/!-^merge", history.side),
+        ] {
+            assert_eq!(
+                repository.rev_parse(revision).unwrap(),
+                hash,
+                "{}",
+                revision
+            );
+        }
+
+        assert_eq!(
+            rev_parse_error(&repository, ":/nothing like this"),
+            RustGitError::UnknownRevision(":/nothing like this".to_string())
+        );
+        assert!(matches!(
+            rev_parse_error(&repository, ":/!x"),
+            RustGitError::InvalidRevision { .. }
+        ));
+    }
+
+    #[test]
+    fn short_names_are_looked_up_in_git_order() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        let short_hash = &history.first.to_string()[..7];
+        assert_eq!(
+            test_repo.open().rev_parse(short_hash).unwrap(),
+            history.first
+        );
+
+        // A ref named like an abbreviated hash wins over the hash,
+        // and tags win over branches, which win over remote-tracking branches
+        let refs = [
+            (format!("refs/tags/{}", short_hash), history.merge),
+            (format!("refs/heads/{}", short_hash), history.side),
+            (format!("refs/remotes/{}", short_hash), history.second),
+        ];
+        for (name, hash) in &refs {
+            test_repo.write(name, format!("{}\n", hash));
+        }
+        for (name, hash) in &refs {
+            assert_eq!(
+                test_repo.open().rev_parse(short_hash).unwrap(),
+                *hash,
+                "{}",
+                name
+            );
+            std::fs::remove_file(test_repo.git_dir().join(name)).unwrap();
+        }
+        assert_eq!(
+            test_repo.open().rev_parse(short_hash).unwrap(),
+            history.first
+        );
+
+        // A remote's name stands for its default branch
+        test_repo.write(
+            "refs/remotes/origin/HEAD",
+            "ref: refs/remotes/origin/main\n",
+        );
+        test_repo.write("refs/remotes/origin/main", format!("{}\n", history.first));
+        assert_eq!(test_repo.open().rev_parse("origin").unwrap(), history.first);
+    }
+
+    #[test]
+    fn missing_and_ambiguous_revisions() {
+        let test_repo = TestRepo::new();
+        let history = write_history(&test_repo);
+        // A blob and a tree whose hashes both start with "87486"
+        let blob = test_repo.write_object(ObjectType::Blob, b"blob 36\n");
+        let tree = test_repo.write_object(ObjectType::Tree, b"tree 3760\n");
+        let repository = test_repo.open();
+
+        for revision in ["main^3", "main~3", "main^2^2", "nothing", "main@{u}"] {
+            let error = rev_parse_error(&repository, revision);
+            assert_eq!(
+                error.kind(),
+                ErrorKind::NotFound,
+                "{}: {:?}",
+                revision,
+                error
+            );
+        }
+        assert_eq!(
+            rev_parse_error(&repository, "main^3"),
+            RustGitError::UnknownRevision("main^3".to_string())
+        );
+        assert_eq!(
+            rev_parse_error(&repository, &format!("{}^", history.first)),
+            RustGitError::UnknownRevision(format!("{}^", history.first))
+        );
+        assert_eq!(
+            rev_parse_error(&repository, "87486"),
+            RustGitError::AmbiguousRevision(crate::abbrev::Ambiguous {
+                prefix: HashPrefix::parse("87486").unwrap(),
+                candidates: vec![
+                    (tree, Some(ObjectType::Tree)),
+                    (blob, Some(ObjectType::Blob))
+                ],
+            })
+        );
+        assert!(matches!(
+            rev_parse_error(&repository, "main~x"),
+            RustGitError::InvalidRevision { .. }
+        ));
+    }
+}
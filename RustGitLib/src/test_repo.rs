@@ -0,0 +1,289 @@
+// Small repositories built on disk for tests, removed again when dropped
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::hash::{Hash, ObjectFormat};
+use crate::object::{with_header, ObjectType};
+use crate::pack::INDEX_MAGIC;
+use crate::repository::Repository;
+
+// Tests run in parallel, so each repository gets its own directory
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestRepo {
+    work_tree: PathBuf,
+    format: ObjectFormat,
+}
+
+/// A pack written by `TestRepo::write_pack()`
+pub struct TestPack {
+    pub pack_path: PathBuf,
+    pub index_path: PathBuf,
+    /// Each object's hash and offset in the pack, in the order given
+    pub objects: Vec<(Hash, u64)>,
+}
+
+/// An entry in a pack written by `TestRepo::write_pack_entries()`
+pub enum TestEntry<'a> {
+    /// A whole object, whose header gives the size paired with it
+    Whole(ObjectType, &'a [u8], u64),
+    /// A delta against the entry at an earlier position in the pack,
+    /// which rebuilds `object`
+    OffsetDelta {
+        base: usize,
+        delta: &'a [u8],
+        object: (ObjectType, &'a [u8]),
+    },
+    /// A delta against an object named by its hash, which rebuilds `object`
+    RefDelta {
+        base: Hash,
+        delta: &'a [u8],
+        object: (ObjectType, &'a [u8]),
+    },
+}
+
+// The header in front of a pack entry: 3 bits of type,
+// then the size, 4 bits in the first byte and 7 in each after
+fn entry_header(type_bits: u8, size: u64) -> Vec<u8> {
+    let mut header = vec![(type_bits << 4) | (size & 0b1111) as u8];
+    let mut size = size >> 4;
+    while size != 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+    header
+}
+
+fn type_bits(object_type: ObjectType) -> u8 {
+    match object_type {
+        ObjectType::Commit => 1,
+        ObjectType::Tree => 2,
+        ObjectType::Blob => 3,
+        ObjectType::Tag => 4,
+    }
+}
+
+// How far back an OFS_DELTA's base is. Each continuation byte
+// stands for one more than its bits, so the encoding is unique.
+fn offset_delta_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// A fan-out table for some sorted hashes
+pub fn fanout_bytes(hashes: &[Hash]) -> Vec<u8> {
+    let mut fanout = vec![];
+    for first_byte in 0..=u8::MAX {
+        let objects = hashes
+            .iter()
+            .filter(|hash| hash.first_byte() <= first_byte)
+            .count();
+        fanout.extend_from_slice(&(objects as u32).to_be_bytes());
+    }
+    fanout
+}
+
+/// A file laid out like a multi-pack-index or commit-graph:
+/// the header, a table of contents, then the chunks
+pub fn chunk_file(header: &[u8], chunks: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut data = header.to_vec();
+    let mut offset = (header.len() + (chunks.len() + 1) * 12) as u64;
+    for (id, contents) in chunks {
+        data.extend_from_slice(id);
+        data.extend_from_slice(&offset.to_be_bytes());
+        offset += contents.len() as u64;
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&offset.to_be_bytes());
+    for (_, contents) in chunks {
+        data.extend_from_slice(contents);
+    }
+    data
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+impl TestRepo {
+    /// Makes an empty SHA-1 repository whose HEAD is the unborn branch `main`
+    pub fn new() -> TestRepo {
+        TestRepo::with_format(ObjectFormat::Sha1)
+    }
+
+    /// Makes an empty repository naming its objects with a hash function,
+    /// whose HEAD is the unborn branch `main`
+    pub fn with_format(format: ObjectFormat) -> TestRepo {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let work_tree = std::env::temp_dir().join(format!("rustgit-test-{}-{}", process::id(), id));
+        let _ = fs::remove_dir_all(&work_tree);
+        let test_repo = TestRepo { work_tree, format };
+        for directory in ["objects/pack", "refs/heads", "refs/tags"] {
+            fs::create_dir_all(test_repo.git_dir().join(directory)).unwrap();
+        }
+        test_repo.write("HEAD", "ref: refs/heads/main\n");
+        if format != ObjectFormat::Sha1 {
+            test_repo.write(
+                "config",
+                format!(
+                    "[core]\n\trepositoryformatversion = 1\n[extensions]\n\tobjectFormat = {}\n",
+                    format
+                ),
+            );
+        }
+        test_repo
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    pub fn git_dir(&self) -> PathBuf {
+        self.work_tree.join(".git")
+    }
+
+    pub fn open(&self) -> Repository {
+        Repository::open(&self.git_dir(), Some(self.work_tree.clone())).unwrap()
+    }
+
+    /// Writes a file under the git directory, e.g. "refs/heads/main"
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) {
+        let path = self.git_dir().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Writes a loose object and returns its hash
+    pub fn write_object(&self, object_type: ObjectType, contents: &[u8]) -> Hash {
+        let object = with_header(object_type, contents);
+        let hash = self.format.digest(&object);
+        self.write_loose_object(hash, &object);
+        hash
+    }
+
+    /// Writes an object, header and all, as the loose object for a hash,
+    /// whether or not it hashes to that
+    pub fn write_loose_object(&self, hash: Hash, object: &[u8]) {
+        let hex_hash = hash.to_string();
+        self.write(
+            format!("objects/{}/{}", &hex_hash[..2], &hex_hash[2..]),
+            compress(object),
+        );
+    }
+
+    /// Writes a pack of whole objects and a version 2 index for it
+    pub fn write_pack(&self, objects: &[(ObjectType, &[u8])]) -> TestPack {
+        let objects: Vec<_> = objects
+            .iter()
+            .map(|&(object_type, contents)| (object_type, contents, contents.len() as u64))
+            .collect();
+        self.write_pack_with_sizes(&objects)
+    }
+
+    /// Like `write_pack()`, but each entry's header gives the size
+    /// paired with it rather than its real size
+    pub fn write_pack_with_sizes(&self, objects: &[(ObjectType, &[u8], u64)]) -> TestPack {
+        let entries: Vec<_> = objects
+            .iter()
+            .map(|&(object_type, contents, size)| TestEntry::Whole(object_type, contents, size))
+            .collect();
+        self.write_pack_entries(&entries)
+    }
+
+    /// Writes a pack of whole objects and deltas, and a version 2 index for it
+    pub fn write_pack_entries(&self, objects: &[TestEntry]) -> TestPack {
+        let format = self.format;
+        let mut pack = b"PACK".to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+        // Each object's hash, offset and CRC32 of its entry
+        let mut entries: Vec<(Hash, u64, u32)> = vec![];
+        for object in objects {
+            let offset = pack.len() as u64;
+            let (mut entry, data, (object_type, contents)) = match *object {
+                TestEntry::Whole(object_type, contents, size) => (
+                    entry_header(type_bits(object_type), size),
+                    contents,
+                    (object_type, contents),
+                ),
+                TestEntry::OffsetDelta {
+                    base,
+                    delta,
+                    object,
+                } => {
+                    let mut entry = entry_header(6, delta.len() as u64);
+                    entry.extend_from_slice(&offset_delta_distance(offset - entries[base].1));
+                    (entry, delta, object)
+                }
+                TestEntry::RefDelta {
+                    base,
+                    delta,
+                    object,
+                } => {
+                    let mut entry = entry_header(7, delta.len() as u64);
+                    entry.extend_from_slice(base.as_bytes());
+                    (entry, delta, object)
+                }
+            };
+            entry.extend_from_slice(&compress(data));
+            let hash = format.digest(&with_header(object_type, contents));
+            entries.push((hash, offset, crc32fast::hash(&entry)));
+            pack.extend_from_slice(&entry);
+        }
+        let pack_checksum = format.digest(&pack);
+        pack.extend_from_slice(pack_checksum.as_bytes());
+
+        let mut sorted = entries.clone();
+        sorted.sort();
+        let mut index = INDEX_MAGIC.to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        let hashes: Vec<Hash> = sorted.iter().map(|&(hash, _, _)| hash).collect();
+        index.extend_from_slice(&fanout_bytes(&hashes));
+        for (hash, _, _) in &sorted {
+            index.extend_from_slice(hash.as_bytes());
+        }
+        for (_, _, crc32) in &sorted {
+            index.extend_from_slice(&crc32.to_be_bytes());
+        }
+        for (_, offset, _) in &sorted {
+            index.extend_from_slice(&(*offset as u32).to_be_bytes());
+        }
+        index.extend_from_slice(pack_checksum.as_bytes());
+        let index_checksum = format.digest(&index);
+        index.extend_from_slice(index_checksum.as_bytes());
+
+        let name = format!("objects/pack/pack-{}", pack_checksum);
+        self.write(format!("{}.pack", name), pack);
+        self.write(format!("{}.idx", name), index);
+        TestPack {
+            pack_path: self.git_dir().join(format!("{}.pack", name)),
+            index_path: self.git_dir().join(format!("{}.idx", name)),
+            objects: entries
+                .into_iter()
+                .map(|(hash, offset, _)| (hash, offset))
+                .collect(),
+        }
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.work_tree);
+    }
+}